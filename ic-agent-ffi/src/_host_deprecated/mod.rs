@@ -1,3 +0,0 @@
-mod keystore;
-
-pub use keystore::{HostKeyStore, HostKeyStoreMeta};
@@ -0,0 +1,127 @@
+use crate::AnyResult;
+use anyhow::bail;
+use std::time::Duration;
+
+/// The configuration of an [`AgentWrapper`](super::AgentWrapper), applied to every call on it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AgentConfig {
+    /// The lifetime(in seconds) of a request before the replica refuses it.
+    pub ingress_expiry_secs: u64,
+    /// The first interval(in milliseconds) between two polls of an update call.
+    pub polling_interval_ms: u64,
+    /// The factor by which the polling interval grows after each poll, `1.0` keeps it constant.
+    pub polling_backoff: f32,
+    /// The upper bound(in milliseconds) of the polling interval.
+    pub polling_max_interval_ms: u64,
+    /// The time(in seconds) to wait for an update call before giving up.
+    pub timeout_secs: u64,
+    /// Trust the root key served by the replica, only for a local/dev network.
+    ///
+    /// Otherwise the hardcoded root key of the IC mainnet is used.
+    pub fetch_root_key: bool,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            ingress_expiry_secs: 60 * 5,
+            polling_interval_ms: 500,
+            polling_backoff: 1.4,
+            polling_max_interval_ms: 1000 * 5,
+            timeout_secs: 60 * 5,
+            fetch_root_key: false,
+        }
+    }
+}
+
+impl AgentConfig {
+    pub fn ingress_expiry(&self) -> Duration {
+        Duration::from_secs(self.ingress_expiry_secs)
+    }
+
+    /// Reject the configurations which `garcon` would take without complaint but never work.
+    pub fn check(&self) -> AnyResult<()> {
+        if self.timeout_secs == 0 {
+            bail!("The timeout must be positive");
+        }
+        if self.polling_backoff.is_nan() || self.polling_backoff < 1.0 {
+            bail!("The polling backoff must be at least 1.0");
+        }
+        if self.polling_interval_ms > self.polling_max_interval_ms {
+            bail!("The polling interval must not exceed the max interval");
+        }
+
+        Ok(())
+    }
+
+    /// The waiter used to poll the status of update calls.
+    pub fn waiter(&self) -> garcon::Delay {
+        garcon::Delay::builder()
+            .exponential_backoff_capped(
+                Duration::from_millis(self.polling_interval_ms),
+                self.polling_backoff,
+                Duration::from_millis(self.polling_max_interval_ms),
+            )
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .build()
+    }
+}
+
+/// Fill `ptr_config` with the default configuration, for callers to modify the fields they need.
+#[no_mangle]
+pub extern "C" fn agent_config_default(ptr_config: *mut AgentConfig) {
+    unsafe {
+        *ptr_config = AgentConfig::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agent_config_default_should_work() {
+        let mut config = AgentConfig {
+            ingress_expiry_secs: 0,
+            polling_interval_ms: 0,
+            polling_backoff: 0.0,
+            polling_max_interval_ms: 0,
+            timeout_secs: 0,
+            fetch_root_key: true,
+        };
+
+        agent_config_default(&mut config);
+
+        assert_eq!(config, AgentConfig::default());
+    }
+
+    #[test]
+    fn agent_config_check_should_fail() {
+        assert!(AgentConfig::default().check().is_ok());
+
+        let configs = [
+            AgentConfig {
+                timeout_secs: 0,
+                ..AgentConfig::default()
+            },
+            AgentConfig {
+                polling_backoff: 0.9,
+                ..AgentConfig::default()
+            },
+            AgentConfig {
+                polling_backoff: f32::NAN,
+                ..AgentConfig::default()
+            },
+            AgentConfig {
+                polling_interval_ms: 1000,
+                polling_max_interval_ms: 999,
+                ..AgentConfig::default()
+            },
+        ];
+
+        for config in configs {
+            assert!(config.check().is_err(), "{config:?}");
+        }
+    }
+}
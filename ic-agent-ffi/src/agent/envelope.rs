@@ -0,0 +1,28 @@
+use crate::{AnyErr, AnyResult};
+use ic_types::Principal;
+use serde::Deserialize;
+
+/// The fields of the content in a signed envelope, which are needed to submit it and to decode
+/// its reply.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct EnvelopeContent {
+    pub request_type: String,
+    pub canister_id: Principal,
+    pub method_name: String,
+    #[serde(with = "serde_bytes")]
+    pub arg: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    content: EnvelopeContent,
+}
+
+impl EnvelopeContent {
+    /// Read the content of a CBOR encoded envelope of a query or call request.
+    pub fn inspect(envelope: &[u8]) -> AnyResult<Self> {
+        let envelope: Envelope = serde_cbor::from_slice(envelope).map_err(AnyErr::from)?;
+
+        Ok(envelope.content)
+    }
+}
@@ -1,137 +1,438 @@
-use crate::identity::IdentityType;
+mod config;
+mod envelope;
+mod transport;
+
+pub use config::AgentConfig;
+pub use envelope::EnvelopeContent;
+pub use transport::{attach_delegations, DelegatedTransport};
+
+use crate::candid::idl_type::{ret_types, IDLType};
+use crate::identity::{IdentityHandle, SignedDelegation};
+use crate::request::RequestHandle;
 use crate::{ret_thin_ptr, ret_unsized, AnyErr, AnyResult, StateCode, UnsizedCallBack};
-use anyhow::{anyhow, bail, Context};
+use anyhow::{bail, Context};
 use candid::types::{Function, Type};
 use candid::{check_prog, CandidType, Decode, Deserialize, IDLArgs, IDLProg, TypeEnv};
 use ic_agent::agent::http_transport::ReqwestHttpReplicaV2Transport;
 use ic_agent::agent::status::Status;
-use ic_agent::identity::{AnonymousIdentity, Identity, Secp256k1Identity};
-use ic_agent::Agent;
+use ic_agent::agent::ReplicaV2Transport;
+use ic_agent::identity::Identity;
+use ic_agent::{Agent, RequestId};
 use ic_types::Principal;
 use ic_utils::interfaces::management_canister::builders::{CanisterInstall, CanisterSettings};
 use ic_utils::interfaces::management_canister::MgmtMethod;
+use lazy_static::lazy_static;
 use libc::{c_char, c_int};
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::fmt::Display;
+use std::future::Future;
 use std::str::FromStr;
-use std::sync::Arc;
-use tokio::runtime;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task::JoinHandle;
+
+/// A callback used to give the result of an asynchronous call to caller.
+type AsyncRetCallBack = extern "C" fn(u64, *const IDLArgs);
+
+/// A callback used to give the error of an asynchronous call to caller.
+type AsyncErrCallBack = extern "C" fn(u64, *const u8, c_int);
+
+lazy_static! {
+    /// The in-flight asynchronous calls, keyed by request id.
+    static ref IN_FLIGHT: Mutex<HashMap<u64, JoinHandle<()>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Where the root key of an [`AgentWrapper`] comes from, once it is known.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RootKey {
+    Fetched,
+    Pinned,
+}
 
 #[derive(Clone, Debug)]
 pub struct AgentWrapper {
+    #[allow(dead_code)]
     url: String,
+    #[allow(dead_code)]
     identity: Arc<dyn Identity>,
+    delegations: Option<Vec<SignedDelegation>>,
     canister_id: Principal,
-    did_content: String,
+    ty_env: Arc<TypeEnv>,
+    actor: Arc<Type>,
+    agent: Agent,
+    config: AgentConfig,
+    runtime: Option<Arc<Runtime>>,
+    root_key: Arc<AsyncMutex<Option<RootKey>>>,
+}
+
+impl Drop for AgentWrapper {
+    fn drop(&mut self) {
+        // The calls in flight hold clones of the agent, which keep the runtime alive until they are
+        // done, even if the agent has been freed.
+        let Some(Ok(runtime)) = self.runtime.take().map(Arc::try_unwrap) else {
+            return;
+        };
+
+        // The last owner is a call running on this runtime, which can't shut it down from there.
+        if Handle::try_current().is_ok() {
+            std::thread::spawn(move || runtime.shutdown_background());
+        } else {
+            runtime.shutdown_background();
+        }
+    }
 }
 
 impl AgentWrapper {
-    pub unsafe fn from_raw(ptr: *mut Self) -> Self {
-        let boxed = Box::from_raw(ptr);
-        let cloned = boxed.clone();
+    pub fn new(
+        url: String,
+        identity: &IdentityHandle,
+        canister_id: Principal,
+        did_content: Option<&str>,
+        config: AgentConfig,
+    ) -> AnyResult<Self> {
+        // Without candid, the agent can only make the raw calls.
+        let (ty_env, actor) = match did_content {
+            Some(did_content) => Self::parse_candid_file(did_content)?,
+            None => (TypeEnv::new(), Type::Service(Vec::new())),
+        };
+
+        let transport = ReqwestHttpReplicaV2Transport::create(&url).map_err(AnyErr::from)?;
+        let transport: Arc<dyn ReplicaV2Transport> = match identity.chain() {
+            Some(chain) => Arc::new(DelegatedTransport::new(
+                transport,
+                chain.delegations.clone(),
+            )),
+            None => Arc::new(transport),
+        };
+
+        let delegations = identity.chain().map(|chain| chain.delegations.clone());
+        let identity = identity.identity();
+
+        let agent = Agent::builder()
+            .with_arc_transport(transport)
+            .with_arc_identity(identity.clone())
+            .with_ingress_expiry(Some(config.ingress_expiry()))
+            .build()
+            .map_err(AnyErr::from)?;
+
+        let runtime = Runtime::new().map_err(AnyErr::from)?;
+
+        Ok(Self {
+            url,
+            identity,
+            delegations,
+            canister_id,
+            ty_env: Arc::new(ty_env),
+            actor: Arc::new(actor),
+            agent,
+            config,
+            runtime: Some(Arc::new(runtime)),
+            root_key: Arc::new(AsyncMutex::new(None)),
+        })
+    }
+
+    /// Borrow the [`AgentWrapper`] behind a pointer handed out by [`agent_create`].
+    pub unsafe fn from_ptr<'a>(ptr: *const Self) -> &'a Self {
+        &*ptr
+    }
+
+    fn runtime(&self) -> &Runtime {
+        // It's taken only when the agent is dropped.
+        self.runtime.as_deref().unwrap()
+    }
+
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime().block_on(future)
+    }
+
+    /// Run `future` on the runtime of this agent without blocking, the outcome is given to
+    /// `ret_cb` or `err_cb` from a thread of that runtime.
+    pub fn spawn<F>(&self, future: F, ret_cb: AsyncRetCallBack, err_cb: AsyncErrCallBack) -> u64
+    where
+        F: Future<Output = AnyResult<IDLArgs>> + Send + 'static,
+    {
+        let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+
+        // Hold the lock until the handle is registered, so the task can't deregister too early.
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+
+        let handle = self.runtime().spawn(async move {
+            let r = future.await;
 
-        // Don't drop the [`AgentWrapper`]
-        Box::into_raw(boxed);
+            // The call has been cancelled if it's no longer in flight, even if it's done here.
+            if IN_FLIGHT.lock().unwrap().remove(&request_id).is_some() {
+                ret_async(request_id, ret_cb, err_cb, r);
+            }
+        });
+
+        in_flight.insert(request_id, handle);
+
+        request_id
+    }
 
-        *cloned
+    /// Run `future` on the runtime of this agent without blocking, the outcome is polled from
+    /// the returned [`RequestHandle`].
+    pub fn begin<F>(&self, future: F) -> RequestHandle
+    where
+        F: Future<Output = AnyResult<IDLArgs>> + Send + 'static,
+    {
+        RequestHandle::spawn(self.runtime(), future)
+    }
+
+    /// The signature of `func_name` in the candid of this agent, along with the [`TypeEnv`] in which
+    /// its types are defined.
+    pub fn method_signature(&self, func_name: &str) -> AnyResult<(Arc<TypeEnv>, Function)> {
+        let func_sig = Self::get_method_signature(func_name, &self.ty_env, &self.actor)?;
+
+        Ok((self.ty_env.clone(), func_sig))
     }
 
     pub async fn query(&self, func_name: &str, func_args: &str) -> AnyResult<IDLArgs> {
-        let (ty_env, actor) = self.parse_candid_file()?;
-        let func_sig = Self::get_method_signature(func_name, &ty_env, &actor)?;
+        let ty_env = self.ty_env.as_ref();
+        let func_sig = Self::get_method_signature(func_name, ty_env, &self.actor)?;
+
+        let args_blb = Self::blob_from_raw(func_args, ty_env, &func_sig)?;
 
-        let args_blb = Self::blob_from_raw(func_args, &ty_env, &func_sig)?;
+        let rst_blb = self.query_raw(func_name, args_blb).await?;
 
+        let rst_idl = Self::idl_from_blob(rst_blb.as_slice(), ty_env, &func_sig)?;
+
+        Ok(rst_idl)
+    }
+
+    pub async fn update(&self, func_name: &str, func_args: &str) -> AnyResult<IDLArgs> {
+        let ty_env = self.ty_env.as_ref();
+        let func_sig = Self::get_method_signature(func_name, ty_env, &self.actor)?;
+        let args_blb = Self::blob_from_raw(func_args, ty_env, &func_sig)?;
+
+        let rst_blb = self.update_raw(func_name, args_blb).await?;
+
+        let rst_idl = Self::idl_from_blob(rst_blb.as_slice(), ty_env, &func_sig)?;
+
+        Ok(rst_idl)
+    }
+
+    /// Call a query method with the candid encoded `args_blb`, give the encoded reply.
+    ///
+    /// Neither the args nor the reply is checked against the candid of this agent.
+    pub async fn query_raw(&self, func_name: &str, args_blb: Vec<u8>) -> AnyResult<Vec<u8>> {
         let effective_canister_id =
-            Self::get_effective_canister_id(func_args, args_blb.as_slice(), &self.canister_id)?;
+            Self::get_effective_canister_id(func_name, args_blb.as_slice(), &self.canister_id)?;
 
-        let agent = self.create_agent().await?;
+        self.ensure_root_key().await?;
 
-        let rst_blb = agent
+        self.agent
             .query(&self.canister_id, func_name)
             .with_arg(args_blb)
             .with_effective_canister_id(effective_canister_id)
             .call()
             .await
-            .map_err(AnyErr::from)?;
+            .map_err(AnyErr::from)
+    }
+
+    /// Call an update method with the candid encoded `args_blb`, wait for the encoded reply.
+    ///
+    /// Neither the args nor the reply is checked against the candid of this agent.
+    pub async fn update_raw(&self, func_name: &str, args_blb: Vec<u8>) -> AnyResult<Vec<u8>> {
+        let effective_canister_id =
+            Self::get_effective_canister_id(func_name, args_blb.as_slice(), &self.canister_id)?;
 
-        let rst_idl = Self::idl_from_blob(rst_blb.as_slice(), &ty_env, &func_sig)?;
+        self.ensure_root_key().await?;
 
-        Ok(rst_idl)
+        self.agent
+            .update(&self.canister_id, func_name)
+            .with_arg(args_blb)
+            .with_effective_canister_id(effective_canister_id)
+            .call_and_wait(self.config.waiter())
+            .await
+            .map_err(AnyErr::from)
     }
 
-    pub async fn update(&self, func_name: &str, func_args: &str) -> AnyResult<IDLArgs> {
-        let (ty_env, actor) = self.parse_candid_file()?;
-        let func_sig = Self::get_method_signature(func_name, &ty_env, &actor)?;
-        let args_blb = Self::blob_from_raw(func_args, &ty_env, &func_sig)?;
+    /// Sign a query call without sending it, give the CBOR encoded envelope.
+    pub fn sign_query(&self, func_name: &str, func_args: &str) -> AnyResult<Vec<u8>> {
+        let ty_env = self.ty_env.as_ref();
+        let func_sig = Self::get_method_signature(func_name, ty_env, &self.actor)?;
+        let args_blb = Self::blob_from_raw(func_args, ty_env, &func_sig)?;
 
         let effective_canister_id =
-            Self::get_effective_canister_id(func_args, args_blb.as_slice(), &self.canister_id)?;
+            Self::get_effective_canister_id(func_name, args_blb.as_slice(), &self.canister_id)?;
+
+        let signed = self
+            .agent
+            .query(&self.canister_id, func_name)
+            .with_arg(args_blb)
+            .with_effective_canister_id(effective_canister_id)
+            .sign()
+            .map_err(AnyErr::from)?;
+
+        self.attach_delegations(signed.signed_query)
+    }
 
-        let agent = self.create_agent().await?;
+    /// Sign an update call without sending it, give the CBOR encoded envelope and its request id.
+    pub fn sign_update(&self, func_name: &str, func_args: &str) -> AnyResult<(Vec<u8>, RequestId)> {
+        let ty_env = self.ty_env.as_ref();
+        let func_sig = Self::get_method_signature(func_name, ty_env, &self.actor)?;
+        let args_blb = Self::blob_from_raw(func_args, ty_env, &func_sig)?;
 
-        let rst_blb = agent
+        let effective_canister_id =
+            Self::get_effective_canister_id(func_name, args_blb.as_slice(), &self.canister_id)?;
+
+        let signed = self
+            .agent
             .update(&self.canister_id, func_name)
             .with_arg(args_blb)
             .with_effective_canister_id(effective_canister_id)
-            .call_and_wait(
-                garcon::Delay::builder()
-                    .timeout(std::time::Duration::from_secs(60 * 5))
-                    .build(),
-            )
+            .sign()
+            .map_err(AnyErr::from)?;
+
+        let envelope = self.attach_delegations(signed.signed_update)?;
+
+        Ok((envelope, signed.request_id))
+    }
+
+    /// Send a query envelope signed by [`AgentWrapper::sign_query`], maybe of another agent.
+    pub async fn submit_query(&self, envelope: Vec<u8>) -> AnyResult<IDLArgs> {
+        let content = EnvelopeContent::inspect(&envelope)?;
+        let (func_sig, effective_canister_id) = self.inspect_signed(&content, "query")?;
+
+        self.ensure_root_key().await?;
+
+        let rst_blb = self
+            .agent
+            .query_signed(effective_canister_id, envelope)
             .await
             .map_err(AnyErr::from)?;
 
-        let rst_idl = Self::idl_from_blob(rst_blb.as_slice(), &ty_env, &func_sig)?;
+        Self::idl_from_blob(rst_blb.as_slice(), &self.ty_env, &func_sig)
+    }
 
-        Ok(rst_idl)
+    /// Send an update envelope signed by [`AgentWrapper::sign_update`], maybe of another agent,
+    /// then poll `read_state` until the certified reply comes.
+    pub async fn submit_update(&self, envelope: Vec<u8>) -> AnyResult<IDLArgs> {
+        let content = EnvelopeContent::inspect(&envelope)?;
+        let (func_sig, effective_canister_id) = self.inspect_signed(&content, "call")?;
+
+        self.ensure_root_key().await?;
+
+        let request_id = self
+            .agent
+            .update_signed(effective_canister_id, envelope)
+            .await
+            .map_err(AnyErr::from)?;
+        let rst_blb = self
+            .agent
+            .wait(
+                request_id,
+                effective_canister_id,
+                false,
+                self.config.waiter(),
+            )
+            .await
+            .map_err(AnyErr::from)?;
+
+        Self::idl_from_blob(rst_blb.as_slice(), &self.ty_env, &func_sig)
     }
 
     pub async fn status(&self) -> AnyResult<Status> {
-        let agent = self.create_agent().await?;
-
-        agent.status().await.map_err(AnyErr::from)
+        self.agent.status().await.map_err(AnyErr::from)
     }
 
-    fn parse_candid_file(&self) -> AnyResult<(TypeEnv, Option<Type>)> {
-        let ast = self.did_content.parse::<IDLProg>().map_err(AnyErr::from)?;
+    fn parse_candid_file(did_content: &str) -> AnyResult<(TypeEnv, Type)> {
+        let ast = did_content.parse::<IDLProg>().map_err(AnyErr::from)?;
 
         let mut env = TypeEnv::new();
         let actor = check_prog(&mut env, &ast).map_err(AnyErr::from)?;
 
-        Ok((env, actor))
+        match actor {
+            Some(actor) => Ok((env, actor)),
+            None => bail!("The candid file doesn't define a service"),
+        }
     }
 
-    async fn create_agent(&self) -> AnyResult<Agent> {
-        let transport = ReqwestHttpReplicaV2Transport::create(&self.url).map_err(AnyErr::from)?;
+    /// Fetch the root key on the first call made through this agent if the network is a
+    /// local/dev one, and reuse it afterwards.
+    async fn ensure_root_key(&self) -> AnyResult<()> {
+        if !self.config.fetch_root_key {
+            return Ok(());
+        }
+
+        // Fetch under the lock, so a key pinned meanwhile is set after the fetched one.
+        let mut root_key = self.root_key.lock().await;
+        if root_key.is_none() {
+            self.agent.fetch_root_key().await.map_err(AnyErr::from)?;
 
-        let agent = Agent::builder()
-            .with_transport(transport)
-            .with_arc_identity(self.identity.clone())
-            .build()
-            .map_err(AnyErr::from)?;
+            *root_key = Some(RootKey::Fetched);
+        }
+
+        Ok(())
+    }
+
+    /// Pin the root key, which is never replaced by the one fetched by [`Self::ensure_root_key`].
+    pub async fn set_root_key(&self, root_key: Vec<u8>) -> AnyResult<()> {
+        let mut state = self.root_key.lock().await;
+
+        self.agent.set_root_key(root_key).map_err(AnyErr::from)?;
+
+        *state = Some(RootKey::Pinned);
+
+        Ok(())
+    }
 
-        agent.fetch_root_key().await.map_err(AnyErr::from)?;
+    fn attach_delegations(&self, envelope: Vec<u8>) -> AnyResult<Vec<u8>> {
+        match &self.delegations {
+            Some(delegations) => attach_delegations(&envelope, delegations).map_err(AnyErr::from),
+            None => Ok(envelope),
+        }
+    }
+
+    /// Check the signed content is for the canister of this agent, and give the signature of
+    /// method to decode the reply with.
+    fn inspect_signed(
+        &self,
+        content: &EnvelopeContent,
+        request_type: &str,
+    ) -> AnyResult<(Function, Principal)> {
+        if content.request_type != request_type {
+            bail!(
+                "The envelope is a {} request, expected {}",
+                content.request_type,
+                request_type
+            );
+        }
+        if content.canister_id != self.canister_id {
+            bail!(
+                "The envelope is for the canister {}, not {}",
+                content.canister_id,
+                self.canister_id
+            );
+        }
+
+        let func_sig = Self::get_method_signature(&content.method_name, &self.ty_env, &self.actor)?;
+        let effective_canister_id = Self::get_effective_canister_id(
+            &content.method_name,
+            content.arg.as_slice(),
+            &self.canister_id,
+        )?;
 
-        Ok(agent)
+        Ok((func_sig, effective_canister_id))
     }
 
     fn get_method_signature(
         method_name: &str,
         ty_env: &TypeEnv,
-        actor: &Option<Type>,
+        actor: &Type,
     ) -> AnyResult<Function> {
-        match actor {
-            Some(actor) => {
-                let method_sig = ty_env
-                    .get_method(actor, method_name)
-                    .map_err(AnyErr::from)?
-                    .clone();
+        let method_sig = ty_env
+            .get_method(actor, method_name)
+            .map_err(AnyErr::from)?
+            .clone();
 
-                Ok(method_sig)
-            }
-            None => bail!("Failed to get method: {}", method_name),
-        }
+        Ok(method_sig)
     }
 
     fn blob_from_raw(args_raw: &str, ty_env: &TypeEnv, meth_sig: &Function) -> AnyResult<Vec<u8>> {
@@ -207,23 +508,51 @@ impl AgentWrapper {
     }
 }
 
-/// NOTE: Forbid inputting [`BasicIdentity`] because:
-///
-/// 1. `[BasicIdentity]` doesn't implement [`Clone`] trait because it's inner field.
+/// Create an [`AgentWrapper`] with the default [`AgentConfig`].
 #[no_mangle]
 pub extern "C" fn agent_create(
     // Url points to the ic net
     url: *const c_char,
-    // The pointer points to the [`Identity`]
-    p2fptr_iden: *const *const dyn Identity,
-    // The type of [`Identity`]
-    iden_type: IdentityType,
+    // The pointer points to the [`IdentityHandle`]
+    ptr_iden: *const IdentityHandle,
+    // The data of [`Principal`]
+    canister_id_bytes: *const u8,
+    // The length of data of [`Principal`]
+    canister_id_bytes_len: c_int,
+    // The content of candid of that canister, or null to make only the raw calls
+    did_content: *const c_char,
+    // out: A pointer points to the `AgentWrapper`
+    p2ptr_agent_w: *mut *const AgentWrapper,
+    // The callback used report error information
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    agent_create_with_config(
+        url,
+        ptr_iden,
+        canister_id_bytes,
+        canister_id_bytes_len,
+        did_content,
+        &AgentConfig::default(),
+        p2ptr_agent_w,
+        err_cb,
+    )
+}
+
+/// Create an [`AgentWrapper`] whose calls follow `ptr_config`.
+#[no_mangle]
+pub extern "C" fn agent_create_with_config(
+    // Url points to the ic net
+    url: *const c_char,
+    // The pointer points to the [`IdentityHandle`]
+    ptr_iden: *const IdentityHandle,
     // The data of [`Principal`]
     canister_id_bytes: *const u8,
     // The length of data of [`Principal`]
     canister_id_bytes_len: c_int,
-    // The content of candid of that canister
+    // The content of candid of that canister, or null to make only the raw calls
     did_content: *const c_char,
+    // The configuration of the `AgentWrapper`
+    ptr_config: *const AgentConfig,
     // out: A pointer points to the `AgentWrapper`
     p2ptr_agent_w: *mut *const AgentWrapper,
     // The callback used report error information
@@ -232,40 +561,23 @@ pub extern "C" fn agent_create(
     let once = || -> AnyResult<AgentWrapper> {
         let url = unsafe { CStr::from_ptr(url).to_str().map_err(AnyErr::from) }?.to_string();
 
-        let identity = unsafe {
-            match iden_type {
-                IdentityType::Anonymous => Arc::new(AnonymousIdentity {}) as Arc<dyn Identity>,
-                IdentityType::Basic => {
-                    return Err(anyhow!("Forbid using [`BasicIdentity`]"));
-                }
-                IdentityType::Secp256K1 => {
-                    // downcast
-                    let ptr = *p2fptr_iden as *const Secp256k1Identity;
-                    let boxed = Box::from_raw(ptr as *mut Secp256k1Identity);
-                    let cloned = Arc::<Secp256k1Identity>::from(boxed.clone()) as Arc<dyn Identity>;
-
-                    // Make pointer to [`Identity`] usable
-                    Box::into_raw(boxed);
-
-                    cloned
-                }
-            }
-        };
+        // Share the ownership of [`Identity`] with the caller
+        let identity = unsafe { IdentityHandle::from_ptr(ptr_iden) };
 
         let slice = unsafe {
             std::slice::from_raw_parts(canister_id_bytes, canister_id_bytes_len as usize)
         };
         let canister_id = Principal::from_slice(slice);
 
-        let did_content =
-            unsafe { CStr::from_ptr(did_content).to_str().map_err(AnyErr::from) }?.to_string();
+        let did_content = match did_content.is_null() {
+            true => None,
+            false => Some(unsafe { CStr::from_ptr(did_content).to_str().map_err(AnyErr::from) }?),
+        };
 
-        Ok(AgentWrapper {
-            url,
-            identity,
-            canister_id,
-            did_content,
-        })
+        let config = unsafe { *ptr_config };
+        config.check()?;
+
+        AgentWrapper::new(url, identity, canister_id, did_content, config)
     };
 
     __todo_replace_this_by_macro(p2ptr_agent_w, err_cb, once())
@@ -280,12 +592,11 @@ pub extern "C" fn agent_query(
     err_cb: UnsizedCallBack<u8>,
 ) -> StateCode {
     let once = || -> AnyResult<_> {
-        let agent_w = unsafe { AgentWrapper::from_raw(ptr_agent_w as *mut AgentWrapper) };
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
         let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
         let func_args = unsafe { CStr::from_ptr(func_args).to_str().map_err(AnyErr::from) }?;
 
-        let runtime = runtime::Runtime::new()?;
-        let rst_idl = runtime.block_on(agent_w.query(func_name, func_args))?;
+        let rst_idl = agent_w.block_on(agent_w.query(func_name, func_args))?;
 
         Ok(rst_idl)
     };
@@ -302,12 +613,11 @@ pub extern "C" fn agent_update(
     err_cb: UnsizedCallBack<u8>,
 ) -> StateCode {
     let once = || -> AnyResult<_> {
-        let agent_w = unsafe { AgentWrapper::from_raw(ptr_agent_w as *mut AgentWrapper) };
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
         let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
         let func_args = unsafe { CStr::from_ptr(func_args).to_str().map_err(AnyErr::from) }?;
 
-        let runtime = runtime::Runtime::new()?;
-        let rst_idl = runtime.block_on(agent_w.update(func_name, func_args))?;
+        let rst_idl = agent_w.block_on(agent_w.update(func_name, func_args))?;
 
         Ok(rst_idl)
     };
@@ -315,48 +625,177 @@ pub extern "C" fn agent_update(
     crate::candid::idl_args::__todo_replace_this_by_macro(p2ptr, err_cb, once())
 }
 
+/// Call a query method with the candid encoded `args`, e.g. given by `idl_args_to_bytes`, the
+/// encoded reply is given to `ret_cb`.
 #[no_mangle]
-pub extern "C" fn agent_status(
+pub extern "C" fn agent_query_raw(
     ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    args: *const u8,
+    args_len: c_int,
     ret_cb: UnsizedCallBack<u8>,
     err_cb: UnsizedCallBack<u8>,
 ) -> StateCode {
     let once = || -> AnyResult<_> {
-        let agent_w = unsafe { Box::from_raw(ptr_agent_w as *mut AgentWrapper) };
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+        let args = unsafe { std::slice::from_raw_parts(args, args_len as usize) };
 
-        let runtime = runtime::Runtime::new()?;
-        let status = runtime.block_on(agent_w.status())?;
+        agent_w.block_on(agent_w.query_raw(func_name, args.to_vec()))
+    };
 
-        // Don't drop the [`AgentWrapper`]
-        Box::into_raw(agent_w);
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, once())
+}
 
-        let status_cstr = CString::new(status.to_string())
-            .map_err(AnyErr::from)?
-            .into_bytes_with_nul();
+/// Call an update method with the candid encoded `args`, e.g. given by `idl_args_to_bytes`, the
+/// encoded reply is given to `ret_cb`.
+#[no_mangle]
+pub extern "C" fn agent_update_raw(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    args: *const u8,
+    args_len: c_int,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+        let args = unsafe { std::slice::from_raw_parts(args, args_len as usize) };
 
-        Ok(status_cstr)
+        agent_w.block_on(agent_w.update_raw(func_name, args.to_vec()))
     };
 
     crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, once())
 }
 
+/// Call a query method without blocking, return the request id of this call.
+///
+/// The result is given to `ret_cb` and the error to `err_cb`, both from a background thread.
 #[no_mangle]
-pub extern "C" fn agent_free(ptr_agent_w: *const AgentWrapper) {
-    let boxed = unsafe { Box::from_raw(ptr_agent_w as *mut AgentWrapper) };
+pub extern "C" fn agent_query_async(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    func_args: *const c_char,
+    ret_cb: AsyncRetCallBack,
+    err_cb: AsyncErrCallBack,
+) -> u64 {
+    let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+    let func_name = unsafe { CStr::from_ptr(func_name).to_str().map(str::to_string) };
+    let func_args = unsafe { CStr::from_ptr(func_args).to_str().map(str::to_string) };
+    let task_w = agent_w.clone();
+
+    agent_w.spawn(
+        async move {
+            let func_name = func_name?;
+            let func_args = func_args?;
+
+            task_w.query(&func_name, &func_args).await
+        },
+        ret_cb,
+        err_cb,
+    )
+}
 
-    drop(boxed);
+/// Call an update method without blocking, return the request id of this call.
+///
+/// The result is given to `ret_cb` and the error to `err_cb`, both from a background thread.
+#[no_mangle]
+pub extern "C" fn agent_update_async(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    func_args: *const c_char,
+    ret_cb: AsyncRetCallBack,
+    err_cb: AsyncErrCallBack,
+) -> u64 {
+    let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+    let func_name = unsafe { CStr::from_ptr(func_name).to_str().map(str::to_string) };
+    let func_args = unsafe { CStr::from_ptr(func_args).to_str().map(str::to_string) };
+    let task_w = agent_w.clone();
+
+    agent_w.spawn(
+        async move {
+            let func_name = func_name?;
+            let func_args = func_args?;
+
+            task_w.update(&func_name, &func_args).await
+        },
+        ret_cb,
+        err_cb,
+    )
 }
 
-pub(crate) fn __todo_replace_this_by_macro(
-    p2ptr: *mut *const AgentWrapper,
+/// Call a query method without blocking, give a [`RequestHandle`] to poll the result from.
+#[no_mangle]
+pub extern "C" fn agent_query_begin(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    func_args: *const c_char,
+    p2ptr_request: *mut *const RequestHandle,
     err_cb: UnsizedCallBack<u8>,
-    r: Result<AgentWrapper, impl Display>,
 ) -> StateCode {
-    match r {
-        Ok(t) => {
-            unsafe {
-                ret_thin_ptr(p2ptr, t);
-            }
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+        let func_args = unsafe { CStr::from_ptr(func_args).to_str().map_err(AnyErr::from) }?;
+
+        let (func_name, func_args) = (func_name.to_string(), func_args.to_string());
+        let task_w = agent_w.clone();
+
+        let request = agent_w.begin(async move { task_w.query(&func_name, &func_args).await });
+
+        Ok(request)
+    };
+
+    crate::request::__todo_replace_this_by_macro(p2ptr_request, err_cb, once())
+}
+
+/// Call an update method without blocking, give a [`RequestHandle`] to poll the result from.
+#[no_mangle]
+pub extern "C" fn agent_update_begin(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    func_args: *const c_char,
+    p2ptr_request: *mut *const RequestHandle,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+        let func_args = unsafe { CStr::from_ptr(func_args).to_str().map_err(AnyErr::from) }?;
+
+        let (func_name, func_args) = (func_name.to_string(), func_args.to_string());
+        let task_w = agent_w.clone();
+
+        let request = agent_w.begin(async move { task_w.update(&func_name, &func_args).await });
+
+        Ok(request)
+    };
+
+    crate::request::__todo_replace_this_by_macro(p2ptr_request, err_cb, once())
+}
+
+/// Give the handles of argument types to `args_cb` and the ones of return types to `rets_cb`, which
+/// can annotate the values by [`crate::candid::idl_args::idl_args_annotate`].
+#[no_mangle]
+pub extern "C" fn agent_method_types(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    args_cb: UnsizedCallBack<*const IDLType>,
+    rets_cb: UnsizedCallBack<*const IDLType>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+
+        agent_w.method_signature(func_name)
+    };
+
+    match once() {
+        Ok((ty_env, func_sig)) => {
+            ret_types(args_cb, ty_env.clone(), &func_sig.args);
+            ret_types(rets_cb, ty_env, &func_sig.rets);
 
             StateCode::Ok
         }
@@ -368,39 +807,259 @@ pub(crate) fn __todo_replace_this_by_macro(
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::identity::{identity_anonymous, identity_basic_random, identity_secp256k1_random};
-    use crate::tests_util::{apply_fptr, apply_ptr, panic_err_cb};
-    use ic_agent::identity::BasicIdentity;
-    use ic_types::Principal;
-    use libc::c_int;
+/// Sign a query call without sending it, give the CBOR encoded envelope to `ret_cb`.
+#[no_mangle]
+pub extern "C" fn agent_sign_query(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    func_args: *const c_char,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+        let func_args = unsafe { CStr::from_ptr(func_args).to_str().map_err(AnyErr::from) }?;
 
-    const IC_NET_BYTES: &[u8] = b"https://ic0.app\0";
+        agent_w.sign_query(func_name, func_args)
+    };
 
-    const II_CANISTER_ID_BYTES: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 7, 1, 1];
-    const II_DID_CONTENT_BYTES: &[u8] =
-        concat_bytes!(include_bytes!("rdmx6-jaaaa-aaaaa-aaadq-cai.did"), b"\0");
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, once())
+}
 
-    fn cbytes_to_str(cbytes: &[u8]) -> &str {
-        let cstr = CStr::from_bytes_with_nul(cbytes).unwrap();
-        cstr.to_str().unwrap()
+/// Sign an update call without sending it, give the CBOR encoded envelope to `ret_cb` and its
+/// request id to `request_id_cb`.
+#[no_mangle]
+pub extern "C" fn agent_sign_update(
+    ptr_agent_w: *const AgentWrapper,
+    func_name: *const c_char,
+    func_args: *const c_char,
+    ret_cb: UnsizedCallBack<u8>,
+    request_id_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        let func_name = unsafe { CStr::from_ptr(func_name).to_str().map_err(AnyErr::from) }?;
+        let func_args = unsafe { CStr::from_ptr(func_args).to_str().map_err(AnyErr::from) }?;
+
+        agent_w.sign_update(func_name, func_args)
+    };
+
+    match once() {
+        Ok((envelope, request_id)) => {
+            ret_unsized(ret_cb, envelope);
+            ret_unsized(request_id_cb, request_id.as_slice());
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+/// Send a query envelope given by [`agent_sign_query`], the reply is decoded by the candid of
+/// this agent.
+#[no_mangle]
+pub extern "C" fn agent_submit_query(
+    ptr_agent_w: *const AgentWrapper,
+    envelope: *const u8,
+    envelope_len: c_int,
+    p2ptr: *mut *const IDLArgs,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        if envelope.is_null() {
+            bail!("The envelope is null");
+        }
+        let envelope = unsafe { std::slice::from_raw_parts(envelope, envelope_len as usize) };
+
+        agent_w.block_on(agent_w.submit_query(envelope.to_vec()))
+    };
+
+    crate::candid::idl_args::__todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+/// Send an update envelope given by [`agent_sign_update`] and wait for its certified reply, which
+/// is decoded by the candid of this agent.
+#[no_mangle]
+pub extern "C" fn agent_submit_update(
+    ptr_agent_w: *const AgentWrapper,
+    envelope: *const u8,
+    envelope_len: c_int,
+    p2ptr: *mut *const IDLArgs,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+        if envelope.is_null() {
+            bail!("The envelope is null");
+        }
+        let envelope = unsafe { std::slice::from_raw_parts(envelope, envelope_len as usize) };
+
+        agent_w.block_on(agent_w.submit_update(envelope.to_vec()))
+    };
+
+    crate::candid::idl_args::__todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+/// Abort an in-flight asynchronous call, neither of its callbacks will be called afterwards.
+///
+/// Return `false` if the call has already finished or the request id is unknown.
+#[no_mangle]
+pub extern "C" fn agent_cancel(request_id: u64) -> bool {
+    match IN_FLIGHT.lock().unwrap().remove(&request_id) {
+        Some(handle) => {
+            handle.abort();
+
+            true
+        }
+        None => false,
+    }
+}
+
+/// Pin the root key used to verify the certificates from the replica, e.g. of a testnet.
+#[no_mangle]
+pub extern "C" fn agent_set_root_key(
+    ptr_agent_w: *const AgentWrapper,
+    root_key_bytes: *const u8,
+    root_key_bytes_len: c_int,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+    let root_key =
+        unsafe { std::slice::from_raw_parts(root_key_bytes, root_key_bytes_len as usize) };
+
+    match agent_w.block_on(agent_w.set_root_key(root_key.to_vec())) {
+        Ok(()) => StateCode::Ok,
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn agent_status(
+    ptr_agent_w: *const AgentWrapper,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<_> {
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr_agent_w) };
+
+        let status = agent_w.block_on(agent_w.status())?;
+
+        let status_cstr = CString::new(status.to_string())
+            .map_err(AnyErr::from)?
+            .into_bytes_with_nul();
+
+        Ok(status_cstr)
+    };
+
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, once())
+}
+
+/// Free the agent, the calls still in flight keep running and give their results as usual.
+#[no_mangle]
+pub extern "C" fn agent_free(ptr_agent_w: *const AgentWrapper) {
+    let boxed = unsafe { Box::from_raw(ptr_agent_w as *mut AgentWrapper) };
+
+    drop(boxed);
+}
+
+pub(crate) fn __todo_replace_this_by_macro(
+    p2ptr: *mut *const AgentWrapper,
+    err_cb: UnsizedCallBack<u8>,
+    r: Result<AgentWrapper, impl Display>,
+) -> StateCode {
+    match r {
+        Ok(t) => {
+            unsafe {
+                ret_thin_ptr(p2ptr, t);
+            }
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+fn ret_async(
+    request_id: u64,
+    ret_cb: AsyncRetCallBack,
+    err_cb: AsyncErrCallBack,
+    r: AnyResult<IDLArgs>,
+) {
+    match r {
+        Ok(t) => {
+            let raw = Box::into_raw(Box::new(t));
+
+            ret_cb(request_id, raw);
+        }
+        Err(e) => {
+            let err = e.to_string() + "\0";
+
+            err_cb(request_id, err.as_ptr(), err.len() as c_int);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candid::idl_args::{idl_args_free, idl_args_to_bytes};
+    use crate::identity::{
+        identity_anonymous, identity_basic_random, identity_free, identity_secp256k1_random,
+    };
+    use crate::tests_util::{apply_ptr, panic_err_cb};
+    use ic_types::Principal;
+    use libc::c_int;
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Sender};
+    use std::time::{Duration, Instant};
+
+    const IC_NET_BYTES: &[u8] = b"https://ic0.app\0";
+
+    const II_CANISTER_ID_BYTES: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 7, 1, 1];
+    const II_DID_CONTENT_BYTES: &[u8] =
+        concat_bytes!(include_bytes!("rdmx6-jaaaa-aaaaa-aaadq-cai.did"), b"\0");
+
+    fn cbytes_to_str(cbytes: &[u8]) -> &str {
+        let cstr = CStr::from_bytes_with_nul(cbytes).unwrap();
+        cstr.to_str().unwrap()
+    }
+
+    fn ii_actor() -> Type {
+        let (_, actor) =
+            AgentWrapper::parse_candid_file(cbytes_to_str(II_DID_CONTENT_BYTES)).unwrap();
+
+        actor
     }
 
     extern "C" fn empty_cb(_data: *const u8, _len: c_int) {}
 
     #[test]
     fn agent_create_with_anonymous_should_work() {
-        let mut fptr = apply_fptr::<AnonymousIdentity, _>();
-        identity_anonymous(&mut fptr);
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Anonymous,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
@@ -411,34 +1070,33 @@ mod tests {
         );
 
         unsafe {
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
 
             let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
             assert_eq!(agent_w_wrap.url, cbytes_to_str(IC_NET_BYTES));
-            assert_eq!(agent_w_wrap.identity.sender(), identity_boxed.sender());
             assert_eq!(
-                agent_w_wrap.canister_id,
-                Principal::from_slice(II_CANISTER_ID_BYTES)
+                agent_w_wrap.identity.sender(),
+                identity_boxed.identity().sender()
             );
             assert_eq!(
-                agent_w_wrap.did_content,
-                cbytes_to_str(II_DID_CONTENT_BYTES)
+                agent_w_wrap.canister_id,
+                Principal::from_slice(II_CANISTER_ID_BYTES)
             );
+            assert_eq!(agent_w_wrap.actor.as_ref(), &ii_actor());
         }
     }
 
     #[test]
     fn agent_create_with_secp256k1_should_work() {
-        let mut fptr = apply_fptr::<Secp256k1Identity, _>();
-        identity_secp256k1_random(&mut fptr);
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Secp256K1,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
@@ -449,58 +1107,167 @@ mod tests {
         );
 
         unsafe {
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
 
             let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
             assert_eq!(agent_w_wrap.url, cbytes_to_str(IC_NET_BYTES));
-            assert_eq!(agent_w_wrap.identity.sender(), identity_boxed.sender());
             assert_eq!(
-                agent_w_wrap.canister_id,
-                Principal::from_slice(II_CANISTER_ID_BYTES)
+                agent_w_wrap.identity.sender(),
+                identity_boxed.identity().sender()
             );
             assert_eq!(
-                agent_w_wrap.did_content,
-                cbytes_to_str(II_DID_CONTENT_BYTES)
+                agent_w_wrap.canister_id,
+                Principal::from_slice(II_CANISTER_ID_BYTES)
             );
+            assert_eq!(agent_w_wrap.actor.as_ref(), &ii_actor());
+        }
+    }
+
+    #[test]
+    fn agent_create_with_config_should_work() {
+        let config = AgentConfig {
+            ingress_expiry_secs: 60,
+            polling_interval_ms: 200,
+            polling_backoff: 2.0,
+            polling_max_interval_ms: 2000,
+            timeout_secs: 30,
+            fetch_root_key: true,
+        };
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create_with_config(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &config,
+                &mut ptr,
+                empty_cb
+            ),
+            StateCode::Ok
+        );
+
+        unsafe {
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
+
+            let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
+            assert_eq!(agent_w_wrap.config, config);
         }
     }
 
     #[test]
-    fn agent_create_with_basic_should_fail() {
+    fn agent_create_with_config_should_fail() {
         extern "C" fn err_cb(data: *const u8, _len: c_int) {
-            let c_str = unsafe { CStr::from_ptr(data as *const i8) };
+            let c_str = unsafe { CStr::from_ptr(data as *const c_char) };
             let str = c_str.to_str().unwrap();
-            assert_eq!(str, "Forbid using [`BasicIdentity`]");
+            assert_eq!(str, "The polling backoff must be at least 1.0");
         }
 
-        let mut fptr = apply_fptr::<BasicIdentity, _>();
-        identity_basic_random(&mut fptr, empty_cb);
+        let config = AgentConfig {
+            polling_backoff: 0.5,
+            ..AgentConfig::default()
+        };
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create_with_config(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &config,
+                &mut ptr,
+                err_cb
+            ),
+            StateCode::Err
+        );
+        assert!(ptr.is_null());
+
+        // Free here!
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    fn agent_create_with_basic_should_work() {
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_basic_random(&mut ptr_iden, empty_cb);
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Basic,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
                 &mut ptr,
+                empty_cb
+            ),
+            StateCode::Ok
+        );
+
+        // The agent keeps the identity alive after it has been freed by the caller
+        let sender = unsafe { IdentityHandle::from_ptr(ptr_iden) }
+            .identity()
+            .sender();
+        identity_free(ptr_iden);
+
+        unsafe {
+            let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
+            assert!(sender.is_ok());
+            assert_eq!(agent_w_wrap.identity.sender(), sender);
+            assert_eq!(agent_w_wrap.agent.get_principal(), sender);
+        }
+    }
+
+    #[test]
+    fn agent_create_with_ill_typed_did_should_fail() {
+        const ILL_TYPED_DID_BYTES: &[u8] = b"service : { lookup : (Unknown) -> () query }\0";
+
+        extern "C" fn err_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const i8) };
+            let str = c_str.to_str().unwrap();
+            assert!(str.contains("Unknown"));
+        }
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                ILL_TYPED_DID_BYTES.as_ptr() as *const c_char,
+                &mut ptr,
                 err_cb
             ),
             StateCode::Err
         );
 
         unsafe {
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
 
-            assert_eq!(ptr as u64, 0u64);
+            assert!(ptr.is_null());
         }
     }
 
     #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn agent_query_should_work() {
         const EXPECTED: &str = r#"(
   vec {
@@ -514,16 +1281,15 @@ mod tests {
   },
 )"#;
 
-        let mut fptr = apply_fptr::<Secp256k1Identity, _>();
-        identity_secp256k1_random(&mut fptr);
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
 
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Secp256K1,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
@@ -550,90 +1316,245 @@ mod tests {
             let idl_boxed = Box::from_raw(idl_ptr as *mut IDLArgs);
             assert_eq!(EXPECTED, idl_boxed.to_string());
 
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
 
             let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
             assert_eq!(agent_w_wrap.url, cbytes_to_str(IC_NET_BYTES));
-            assert_eq!(agent_w_wrap.identity.sender(), identity_boxed.sender());
             assert_eq!(
-                agent_w_wrap.canister_id,
-                Principal::from_slice(II_CANISTER_ID_BYTES)
+                agent_w_wrap.identity.sender(),
+                identity_boxed.identity().sender()
             );
             assert_eq!(
-                agent_w_wrap.did_content,
-                cbytes_to_str(II_DID_CONTENT_BYTES)
+                agent_w_wrap.canister_id,
+                Principal::from_slice(II_CANISTER_ID_BYTES)
             );
+            assert_eq!(agent_w_wrap.actor.as_ref(), &ii_actor());
         }
     }
 
     #[test]
-    fn agent_update_should_work() {
-        let mut fptr = apply_fptr::<Secp256k1Identity, _>();
-        identity_secp256k1_random(&mut fptr);
+    #[allow(clippy::manual_c_str_literals)]
+    fn agent_query_raw_should_work() {
+        static ARGS: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+        static REPLY: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+        static ERROR: Mutex<String> = Mutex::new(String::new());
+
+        extern "C" fn args_cb(data: *const u8, len: c_int) {
+            let slice = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            *ARGS.lock().unwrap() = slice.to_vec();
+        }
+
+        extern "C" fn reply_cb(data: *const u8, len: c_int) {
+            let slice = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            *REPLY.lock().unwrap() = slice.to_vec();
+        }
 
+        // Panicking in the callback would abort all the tests, e.g. when offline.
+        extern "C" fn err_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const c_char) };
+
+            *ERROR.lock().unwrap() = c_str.to_str().unwrap().to_string();
+        }
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Secp256K1,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
                 &mut ptr,
-                empty_cb
+                panic_err_cb
             ),
             StateCode::Ok
         );
 
-        let mut idl_ptr = apply_ptr::<IDLArgs>();
-
+        let idl_args = Box::into_raw(Box::new("(1974211: nat64)".parse::<IDLArgs>().unwrap()));
         assert_eq!(
-            agent_update(
-                ptr,
-                b"create_challenge\0".as_ptr() as *const c_char,
-                b"()\0".as_ptr() as *const c_char,
-                &mut idl_ptr,
-                panic_err_cb,
-            ),
+            idl_args_to_bytes(idl_args, args_cb, panic_err_cb),
             StateCode::Ok
         );
+        idl_args_free(idl_args);
+
+        let args = ARGS.lock().unwrap().clone();
+        let sc = agent_query_raw(
+            ptr,
+            b"lookup\0".as_ptr() as *const c_char,
+            args.as_ptr(),
+            args.len() as c_int,
+            reply_cb,
+            err_cb,
+        );
+        assert_eq!(ERROR.lock().unwrap().as_str(), "");
+        assert_eq!(sc, StateCode::Ok);
 
-        unsafe {
-            let idl_boxed = Box::from_raw(idl_ptr as *mut IDLArgs);
-            assert!(idl_boxed.to_string().contains("png_base64"));
+        let mut idl_ptr = apply_ptr::<IDLArgs>();
+        let sc = agent_query(
+            ptr,
+            b"lookup\0".as_ptr() as *const c_char,
+            b"(1974211: nat64)\0".as_ptr() as *const c_char,
+            &mut idl_ptr,
+            err_cb,
+        );
+        assert_eq!(ERROR.lock().unwrap().as_str(), "");
+        assert_eq!(sc, StateCode::Ok);
 
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr) };
+        let (ty_env, func_sig) = agent_w.method_signature("lookup").unwrap();
+        let reply = REPLY.lock().unwrap().clone();
+        let decoded = IDLArgs::from_bytes_with_types(&reply, &ty_env, &func_sig.rets).unwrap();
 
-            let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
-            assert_eq!(agent_w_wrap.url, cbytes_to_str(IC_NET_BYTES));
-            assert_eq!(agent_w_wrap.identity.sender(), identity_boxed.sender());
-            assert_eq!(
-                agent_w_wrap.canister_id,
-                Principal::from_slice(II_CANISTER_ID_BYTES)
+        let idl_boxed = unsafe { Box::from_raw(idl_ptr as *mut IDLArgs) };
+        assert_eq!(&decoded, idl_boxed.as_ref());
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn agent_update_should_work() {
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
+
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &mut ptr,
+                empty_cb
+            ),
+            StateCode::Ok
+        );
+
+        let mut idl_ptr = apply_ptr::<IDLArgs>();
+
+        assert_eq!(
+            agent_update(
+                ptr,
+                b"create_challenge\0".as_ptr() as *const c_char,
+                b"()\0".as_ptr() as *const c_char,
+                &mut idl_ptr,
+                panic_err_cb,
+            ),
+            StateCode::Ok
+        );
+
+        unsafe {
+            let idl_boxed = Box::from_raw(idl_ptr as *mut IDLArgs);
+            assert!(idl_boxed.to_string().contains("png_base64"));
+
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
+
+            let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
+            assert_eq!(agent_w_wrap.url, cbytes_to_str(IC_NET_BYTES));
+            assert_eq!(
+                agent_w_wrap.identity.sender(),
+                identity_boxed.identity().sender()
             );
             assert_eq!(
-                agent_w_wrap.did_content,
-                cbytes_to_str(II_DID_CONTENT_BYTES)
+                agent_w_wrap.canister_id,
+                Principal::from_slice(II_CANISTER_ID_BYTES)
             );
+            assert_eq!(agent_w_wrap.actor.as_ref(), &ii_actor());
         }
     }
 
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn agent_call_management_canister_should_fail() {
+        const MGMT_DID_CONTENT_BYTES: &[u8] = b"service : { raw_rand : () -> (blob) }\0";
+
+        static ERROR: Mutex<String> = Mutex::new(String::new());
+
+        extern "C" fn err_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const c_char) };
+
+            *ERROR.lock().unwrap() = c_str.to_str().unwrap().to_string();
+        }
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+
+        let management_canister_id = Principal::management_canister();
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                management_canister_id.as_slice().as_ptr(),
+                management_canister_id.as_slice().len() as c_int,
+                MGMT_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &mut ptr,
+                panic_err_cb,
+            ),
+            StateCode::Ok
+        );
+
+        // The effective canister id is looked up by the method name, not by the arguments.
+        let mut idl_ptr = apply_ptr::<IDLArgs>();
+        assert_eq!(
+            agent_query(
+                ptr,
+                b"raw_rand\0".as_ptr() as *const c_char,
+                b"()\0".as_ptr() as *const c_char,
+                &mut idl_ptr,
+                err_cb,
+            ),
+            StateCode::Err
+        );
+        assert_eq!(
+            ERROR.lock().unwrap().as_str(),
+            "raw_rand can only be called via an inter-canister call."
+        );
+
+        ERROR.lock().unwrap().clear();
+        assert_eq!(
+            agent_update(
+                ptr,
+                b"raw_rand\0".as_ptr() as *const c_char,
+                b"()\0".as_ptr() as *const c_char,
+                &mut idl_ptr,
+                err_cb,
+            ),
+            StateCode::Err
+        );
+        assert_eq!(
+            ERROR.lock().unwrap().as_str(),
+            "raw_rand can only be called via an inter-canister call."
+        );
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
     #[test]
     fn agent_status_should_work() {
-        let mut fptr = apply_fptr::<Secp256k1Identity, _>();
-        identity_secp256k1_random(&mut fptr);
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
 
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Secp256K1,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
@@ -646,34 +1567,544 @@ mod tests {
         assert_eq!(agent_status(ptr, empty_cb, panic_err_cb), StateCode::Ok);
 
         unsafe {
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
 
             let agent_w_wrap = Box::from_raw(ptr as *mut AgentWrapper);
             assert_eq!(agent_w_wrap.url, cbytes_to_str(IC_NET_BYTES));
-            assert_eq!(agent_w_wrap.identity.sender(), identity_boxed.sender());
             assert_eq!(
-                agent_w_wrap.canister_id,
-                Principal::from_slice(II_CANISTER_ID_BYTES)
+                agent_w_wrap.identity.sender(),
+                identity_boxed.identity().sender()
             );
             assert_eq!(
-                agent_w_wrap.did_content,
-                cbytes_to_str(II_DID_CONTENT_BYTES)
+                agent_w_wrap.canister_id,
+                Principal::from_slice(II_CANISTER_ID_BYTES)
             );
+            assert_eq!(agent_w_wrap.actor.as_ref(), &ii_actor());
+        }
+    }
+
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn agent_query_async_should_report_error() {
+        static FAILED: Mutex<Option<Sender<u64>>> = Mutex::new(None);
+
+        extern "C" fn ret_cb(_request_id: u64, _ptr: *const IDLArgs) {
+            unreachable!()
+        }
+
+        extern "C" fn err_cb(request_id: u64, _data: *const u8, _len: c_int) {
+            let _ = FAILED.lock().unwrap().as_ref().unwrap().send(request_id);
+        }
+
+        let (failed_tx, failed_rx) = mpsc::channel();
+        *FAILED.lock().unwrap() = Some(failed_tx);
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &mut ptr,
+                empty_cb
+            ),
+            StateCode::Ok
+        );
+
+        let request_id = agent_query_async(
+            ptr,
+            b"no_such_method\0".as_ptr() as *const c_char,
+            b"()\0".as_ptr() as *const c_char,
+            ret_cb,
+            err_cb,
+        );
+
+        assert_eq!(
+            failed_rx.recv_timeout(Duration::from_secs(10)),
+            Ok(request_id)
+        );
+
+        assert!(!agent_cancel(request_id));
+
+        agent_free(ptr);
+
+        unsafe {
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
         }
     }
 
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn agent_create_without_did_should_work() {
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+
+        let management_canister_id = Principal::management_canister();
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                management_canister_id.as_slice().as_ptr(),
+                management_canister_id.as_slice().len() as c_int,
+                std::ptr::null(),
+                &mut ptr,
+                panic_err_cb,
+            ),
+            StateCode::Ok
+        );
+
+        // Only the raw calls are available without candid.
+        let mut idl_ptr = apply_ptr::<IDLArgs>();
+        assert_eq!(
+            agent_query(
+                ptr,
+                b"raw_rand\0".as_ptr() as *const c_char,
+                b"()\0".as_ptr() as *const c_char,
+                &mut idl_ptr,
+                empty_cb,
+            ),
+            StateCode::Err
+        );
+
+        // The effective canister id is checked before sending.
+        let args = IDLArgs::new(&[]).to_bytes().unwrap();
+        assert_eq!(
+            agent_update_raw(
+                ptr,
+                b"raw_rand\0".as_ptr() as *const c_char,
+                args.as_ptr(),
+                args.len() as c_int,
+                empty_cb,
+                empty_cb,
+            ),
+            StateCode::Err
+        );
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    fn agent_cancel_should_work_on_finished_request() {
+        static FINISHED: Mutex<Option<Sender<u64>>> = Mutex::new(None);
+
+        extern "C" fn ret_cb(request_id: u64, ptr: *const IDLArgs) {
+            drop(unsafe { Box::from_raw(ptr as *mut IDLArgs) });
+
+            let _ = FINISHED.lock().unwrap().as_ref().unwrap().send(request_id);
+        }
+
+        extern "C" fn err_cb(request_id: u64, _data: *const u8, _len: c_int) {
+            let _ = FINISHED.lock().unwrap().as_ref().unwrap().send(request_id);
+        }
+
+        let (finished_tx, finished_rx) = mpsc::channel();
+        *FINISHED.lock().unwrap() = Some(finished_tx);
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+
+        let mut ptr = apply_ptr::<AgentWrapper>();
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                std::ptr::null(),
+                &mut ptr,
+                panic_err_cb,
+            ),
+            StateCode::Ok
+        );
+
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr) };
+        let request_id = agent_w.spawn(async { Ok(IDLArgs::new(&[])) }, ret_cb, err_cb);
+
+        // Once its result is delivered, the request can't be cancelled.
+        assert_eq!(
+            finished_rx.recv_timeout(Duration::from_secs(10)),
+            Ok(request_id)
+        );
+        assert!(!agent_cancel(request_id));
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    fn agent_cancel_should_fail_on_unknown_request() {
+        assert!(!agent_cancel(u64::MAX));
+    }
+
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn agent_method_types_should_work() {
+        const FUNC_NAME: &[u8] = b"lookup\0";
+
+        static TYPES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        extern "C" fn types_cb(data: *const *const IDLType, len: c_int) {
+            let ptrs = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            for ptr in ptrs {
+                let idl_type = unsafe { Box::from_raw(*ptr as *mut IDLType) };
+                TYPES.lock().unwrap().push(idl_type.to_string());
+            }
+        }
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        agent_create(
+            IC_NET_BYTES.as_ptr() as *const c_char,
+            ptr_iden,
+            II_CANISTER_ID_BYTES.as_ptr(),
+            II_CANISTER_ID_BYTES.len() as c_int,
+            II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+            &mut ptr,
+            panic_err_cb,
+        );
+
+        assert_eq!(
+            agent_method_types(
+                ptr,
+                FUNC_NAME.as_ptr() as *const c_char,
+                types_cb,
+                types_cb,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(
+            *TYPES.lock().unwrap(),
+            vec!["UserNumber".to_string(), "vec DeviceData".to_string()]
+        );
+
+        assert_eq!(
+            agent_method_types(
+                ptr,
+                b"unknown\0".as_ptr() as *const c_char,
+                types_cb,
+                types_cb,
+                empty_cb
+            ),
+            StateCode::Err
+        );
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    fn agent_sign_query_should_work() {
+        const FUNC_NAME: &[u8] = b"lookup\0";
+        const FUNC_ARGS: &[u8] = b"(10000)\0";
+
+        static ENVELOPE: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+        extern "C" fn ret_cb(data: *const u8, len: c_int) {
+            let slice = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            *ENVELOPE.lock().unwrap() = slice.to_vec();
+        }
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        agent_create(
+            IC_NET_BYTES.as_ptr() as *const c_char,
+            ptr_iden,
+            II_CANISTER_ID_BYTES.as_ptr(),
+            II_CANISTER_ID_BYTES.len() as c_int,
+            II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+            &mut ptr,
+            panic_err_cb,
+        );
+
+        assert_eq!(
+            agent_sign_query(
+                ptr,
+                FUNC_NAME.as_ptr() as *const c_char,
+                FUNC_ARGS.as_ptr() as *const c_char,
+                ret_cb,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let envelope = ENVELOPE.lock().unwrap().clone();
+        let content = EnvelopeContent::inspect(&envelope).unwrap();
+        assert_eq!(content.request_type, "query");
+        assert_eq!(content.method_name, "lookup");
+        assert_eq!(
+            content.canister_id,
+            Principal::from_slice(II_CANISTER_ID_BYTES)
+        );
+
+        // A query envelope can't be submitted as an update
+        let mut ptr_idl = apply_ptr::<IDLArgs>();
+        assert_eq!(
+            agent_submit_update(
+                ptr,
+                envelope.as_ptr(),
+                envelope.len() as c_int,
+                &mut ptr_idl,
+                empty_cb
+            ),
+            StateCode::Err
+        );
+
+        // Nor can a null envelope
+        assert_eq!(
+            agent_submit_query(ptr, std::ptr::null(), 0, &mut ptr_idl, empty_cb),
+            StateCode::Err
+        );
+        assert!(ptr_idl.is_null());
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    fn agent_sign_update_should_work() {
+        const FUNC_NAME: &[u8] = b"create_challenge\0";
+        const FUNC_ARGS: &[u8] = b"()\0";
+
+        static ENVELOPE: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+        static REQUEST_ID: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+        extern "C" fn ret_cb(data: *const u8, len: c_int) {
+            let slice = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            *ENVELOPE.lock().unwrap() = slice.to_vec();
+        }
+
+        extern "C" fn request_id_cb(data: *const u8, len: c_int) {
+            let slice = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            *REQUEST_ID.lock().unwrap() = slice.to_vec();
+        }
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_basic_random(&mut ptr_iden, panic_err_cb);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        agent_create(
+            IC_NET_BYTES.as_ptr() as *const c_char,
+            ptr_iden,
+            II_CANISTER_ID_BYTES.as_ptr(),
+            II_CANISTER_ID_BYTES.len() as c_int,
+            II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+            &mut ptr,
+            panic_err_cb,
+        );
+
+        assert_eq!(
+            agent_sign_update(
+                ptr,
+                FUNC_NAME.as_ptr() as *const c_char,
+                FUNC_ARGS.as_ptr() as *const c_char,
+                ret_cb,
+                request_id_cb,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let envelope = ENVELOPE.lock().unwrap().clone();
+        assert_eq!(
+            EnvelopeContent::inspect(&envelope).unwrap().request_type,
+            "call"
+        );
+
+        // The request id is the one of the signed content
+        let value: serde_cbor::Value = serde_cbor::from_slice(&envelope).unwrap();
+        let content = match value {
+            serde_cbor::Value::Map(mut fields) => fields
+                .remove(&serde_cbor::Value::Text("content".to_string()))
+                .unwrap(),
+            _ => panic!("The envelope isn't a map"),
+        };
+        let request_id = crate::identity::request_id_of(&serde_cbor::to_vec(&content).unwrap());
+        assert_eq!(request_id.unwrap().to_vec(), *REQUEST_ID.lock().unwrap());
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
+    #[test]
+    fn agent_set_root_key_should_work() {
+        // Nothing listens here, so fetching the root key would fail.
+        const LOCAL_NET_BYTES: &[u8] = b"http://127.0.0.1:1\0";
+        const ROOT_KEY: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        let config = AgentConfig {
+            fetch_root_key: true,
+            ..AgentConfig::default()
+        };
+
+        assert_eq!(
+            agent_create_with_config(
+                LOCAL_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &config,
+                &mut ptr,
+                empty_cb
+            ),
+            StateCode::Ok
+        );
+
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr) };
+        assert!(agent_w.block_on(agent_w.ensure_root_key()).is_err());
+
+        assert_eq!(
+            agent_set_root_key(
+                ptr,
+                ROOT_KEY.as_ptr(),
+                ROOT_KEY.len() as c_int,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        // The pinned key is kept, rather than fetched on the first call.
+        assert_eq!(
+            *agent_w.block_on(agent_w.root_key.lock()),
+            Some(RootKey::Pinned)
+        );
+        assert!(agent_w.block_on(agent_w.ensure_root_key()).is_ok());
+
+        agent_free(ptr);
+
+        unsafe {
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
+        }
+    }
+
+    #[test]
+    fn agent_set_root_key_should_work_while_fetching() {
+        const FETCHED_KEY: [u8; 4] = [0xca, 0xfe, 0xba, 0xbe];
+        const ROOT_KEY: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+        // A replica which answers the status only when told to.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}\0", listener.local_addr().unwrap());
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        let config = AgentConfig {
+            fetch_root_key: true,
+            ..AgentConfig::default()
+        };
+
+        assert_eq!(
+            agent_create_with_config(
+                url.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &config,
+                &mut ptr,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr) };
+
+        std::thread::scope(|scope| {
+            let fetching = scope.spawn(|| agent_w.block_on(agent_w.ensure_root_key()));
+
+            // The fetch is in flight, and holds the root key until it's answered.
+            let (mut stream, _) = listener.accept().unwrap();
+            assert!(agent_w.root_key.try_lock().is_err());
+
+            let pinning = scope.spawn(|| agent_w.block_on(agent_w.set_root_key(ROOT_KEY.to_vec())));
+
+            // Give the pinning a moment to wait on the lock, it's pinned last either way.
+            std::thread::sleep(Duration::from_millis(100));
+
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            let status = serde_cbor::to_vec(&serde_cbor::Value::Map(BTreeMap::from([
+                (
+                    serde_cbor::Value::Text("ic_api_version".into()),
+                    serde_cbor::Value::Text("0.18.0".into()),
+                ),
+                (
+                    serde_cbor::Value::Text("root_key".into()),
+                    serde_cbor::Value::Bytes(FETCHED_KEY.to_vec()),
+                ),
+            ])))
+            .unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/cbor\r\nContent-Length: {}\r\n\r\n",
+                status.len()
+            )
+            .unwrap();
+            stream.write_all(&status).unwrap();
+
+            assert!(fetching.join().unwrap().is_ok());
+            assert!(pinning.join().unwrap().is_ok());
+        });
+
+        // The fetched key didn't replace the pinned one, nor is it fetched again.
+        drop(listener);
+        assert_eq!(
+            *agent_w.block_on(agent_w.root_key.lock()),
+            Some(RootKey::Pinned)
+        );
+        assert!(agent_w.block_on(agent_w.ensure_root_key()).is_ok());
+
+        // Free here!
+        agent_free(ptr);
+        identity_free(ptr_iden);
+    }
+
     #[test]
     fn agent_free_should_work() {
-        let mut fptr = apply_fptr::<AnonymousIdentity, _>();
-        identity_anonymous(&mut fptr);
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
         let mut ptr = apply_ptr::<AgentWrapper>();
 
         assert_eq!(
             agent_create(
                 IC_NET_BYTES.as_ptr() as *const c_char,
-                &mut fptr,
-                IdentityType::Anonymous,
+                ptr_iden,
                 II_CANISTER_ID_BYTES.as_ptr(),
                 II_CANISTER_ID_BYTES.len() as c_int,
                 II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
@@ -686,8 +2117,57 @@ mod tests {
         agent_free(ptr);
 
         unsafe {
-            let identity_boxed = Box::from_raw(fptr as *mut dyn Identity);
-            assert!(identity_boxed.sender().is_ok());
+            let identity_boxed = Box::from_raw(ptr_iden as *mut IdentityHandle);
+            assert!(identity_boxed.identity().sender().is_ok());
+        }
+    }
+
+    #[test]
+    fn agent_free_should_work_while_pending() {
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+        let mut ptr = apply_ptr::<AgentWrapper>();
+
+        assert_eq!(
+            agent_create(
+                IC_NET_BYTES.as_ptr() as *const c_char,
+                ptr_iden,
+                II_CANISTER_ID_BYTES.as_ptr(),
+                II_CANISTER_ID_BYTES.len() as c_int,
+                II_DID_CONTENT_BYTES.as_ptr() as *const c_char,
+                &mut ptr,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        // A call holding a clone of the agent, like the ones of `agent_query_begin`.
+        let agent_w = unsafe { AgentWrapper::from_ptr(ptr) };
+        let task_w = agent_w.clone();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
+        let request = agent_w.begin(async move {
+            done_rx.await.map_err(AnyErr::from)?;
+
+            // The last owner of the runtime is dropped on it.
+            drop(task_w);
+
+            Ok(IDLArgs::new(&[]))
+        });
+
+        agent_free(ptr);
+        assert_eq!(request.poll(), StateCode::Pending);
+
+        done_tx.send(()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while request.poll() == StateCode::Pending {
+            assert!(Instant::now() < deadline, "The request is never done");
+            std::thread::sleep(Duration::from_millis(10));
         }
+        assert_eq!(request.take().unwrap(), Some(IDLArgs::new(&[])));
+
+        // Free here!
+        drop(request);
+        identity_free(ptr_iden);
     }
 }
@@ -0,0 +1,173 @@
+use crate::identity::SignedDelegation;
+use ic_agent::agent::ReplicaV2Transport;
+use ic_agent::{AgentError, RequestId};
+use ic_types::Principal;
+use serde::de::Error as DeError;
+use serde::Serialize;
+use serde_cbor::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+type AsyncResult<'a, T> = Pin<Box<dyn Future<Output = Result<T, AgentError>> + Send + 'a>>;
+
+/// A [`ReplicaV2Transport`] which attaches `sender_delegation` to the envelopes, since [`Agent`]
+/// can't do that by itself.
+///
+/// [`Agent`]: ic_agent::Agent
+pub struct DelegatedTransport {
+    inner: Arc<dyn ReplicaV2Transport>,
+    delegations: Vec<SignedDelegation>,
+}
+
+impl DelegatedTransport {
+    pub fn new(
+        inner: impl ReplicaV2Transport + 'static,
+        delegations: Vec<SignedDelegation>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            delegations,
+        }
+    }
+
+    fn attach(&self, envelope: Vec<u8>) -> Result<Vec<u8>, serde_cbor::Error> {
+        attach_delegations(&envelope, &self.delegations)
+    }
+}
+
+/// Set `sender_delegation` of the CBOR encoded envelope, which replaces the existing one.
+pub fn attach_delegations(
+    envelope: &[u8],
+    delegations: &[SignedDelegation],
+) -> Result<Vec<u8>, serde_cbor::Error> {
+    let value: Value = serde_cbor::from_slice(envelope)?;
+
+    let mut fields = match value {
+        Value::Tag(_, value) => *value,
+        value => value,
+    };
+
+    match &mut fields {
+        Value::Map(fields) => {
+            let delegations = serde_cbor::value::to_value(delegations)?;
+            fields.insert(Value::Text("sender_delegation".to_string()), delegations);
+        }
+        _ => return Err(DeError::custom("The envelope isn't a map")),
+    }
+
+    let mut serialized_bytes = Vec::new();
+    let mut serializer = serde_cbor::Serializer::new(&mut serialized_bytes);
+    serializer.self_describe()?;
+    fields.serialize(&mut serializer)?;
+
+    Ok(serialized_bytes)
+}
+
+impl ReplicaV2Transport for DelegatedTransport {
+    fn call<'a>(
+        &'a self,
+        effective_canister_id: Principal,
+        envelope: Vec<u8>,
+        request_id: RequestId,
+    ) -> AsyncResult<'a, ()> {
+        Box::pin(async move {
+            let envelope = self.attach(envelope)?;
+
+            self.inner
+                .call(effective_canister_id, envelope, request_id)
+                .await
+        })
+    }
+
+    fn read_state<'a>(
+        &'a self,
+        effective_canister_id: Principal,
+        envelope: Vec<u8>,
+    ) -> AsyncResult<'a, Vec<u8>> {
+        Box::pin(async move {
+            let envelope = self.attach(envelope)?;
+
+            self.inner.read_state(effective_canister_id, envelope).await
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        effective_canister_id: Principal,
+        envelope: Vec<u8>,
+    ) -> AsyncResult<'a, Vec<u8>> {
+        Box::pin(async move {
+            let envelope = self.attach(envelope)?;
+
+            self.inner.query(effective_canister_id, envelope).await
+        })
+    }
+
+    fn status<'a>(&'a self) -> AsyncResult<'a, Vec<u8>> {
+        self.inner.status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn delegated_transport_attach_should_work() {
+        let delegation: SignedDelegation = serde_json::from_str(
+            r#"{ "delegation": { "pubkey": "010203", "expiration": 42 }, "signature": [4, 5, 6] }"#,
+        )
+        .unwrap();
+
+        let transport = DelegatedTransport::new(
+            ic_agent::agent::http_transport::ReqwestHttpReplicaV2Transport::create(
+                "https://ic0.app",
+            )
+            .unwrap(),
+            vec![delegation],
+        );
+
+        let mut envelope = Vec::new();
+        let mut serializer = serde_cbor::Serializer::new(&mut envelope);
+        serializer.self_describe().unwrap();
+        let mut fields = BTreeMap::new();
+        fields.insert("sender_sig", serde_bytes::ByteBuf::from(vec![7u8]));
+        fields.serialize(&mut serializer).unwrap();
+
+        let attached = transport.attach(envelope).unwrap();
+        let value: Value = serde_cbor::from_slice(&attached).unwrap();
+
+        let expected = Value::Array(vec![Value::Map(BTreeMap::from([
+            (
+                Value::Text("delegation".to_string()),
+                Value::Map(BTreeMap::from([
+                    (
+                        Value::Text("pubkey".to_string()),
+                        Value::Bytes(vec![1, 2, 3]),
+                    ),
+                    (Value::Text("expiration".to_string()), Value::Integer(42)),
+                ])),
+            ),
+            (
+                Value::Text("signature".to_string()),
+                Value::Bytes(vec![4, 5, 6]),
+            ),
+        ]))]);
+
+        match value {
+            Value::Map(fields) => {
+                assert_eq!(
+                    fields.get(&Value::Text("sender_sig".to_string())),
+                    Some(&Value::Bytes(vec![7]))
+                );
+                assert_eq!(
+                    fields.get(&Value::Text("sender_delegation".to_string())),
+                    Some(&expected)
+                );
+            }
+            _ => panic!("The envelope isn't a map"),
+        }
+    }
+}
@@ -1,8 +1,11 @@
-use crate::{ret_thin_ptr, ret_unsized, AnyErr, StateCode, UnsizedCallBack};
+use crate::candid::idl_type::IDLType;
+use crate::candid::json;
+use crate::{ret_thin_ptr, ret_unsized, AnyErr, AnyResult, StateCode, UnsizedCallBack};
+use anyhow::bail;
 use candid::parser::value::IDLValue;
 use candid::IDLArgs;
 use libc::{c_char, c_int};
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::fmt::Display;
 use std::ops::Deref;
 use std::str::FromStr;
@@ -114,6 +117,92 @@ pub extern "C" fn idl_args_as_vec(ptr: *const IDLArgs, ret_cb: UnsizedCallBack<*
     ret_unsized(ret_cb, r);
 }
 
+/// Annotate the values with `types`, e.g. the return types given by `agent_method_types`, which
+/// replaces the hashed labels of records and variants by the names in the candid.
+#[no_mangle]
+pub extern "C" fn idl_args_annotate(
+    ptr: *const IDLArgs,
+    types: *const *const IDLType,
+    types_len: c_int,
+    p2ptr: *mut *const IDLArgs,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_args = unsafe { &*ptr };
+    let types = unsafe { std::slice::from_raw_parts(types, types_len as usize) };
+
+    let once = || -> AnyResult<IDLArgs> {
+        if idl_args.args.len() != types.len() {
+            bail!(
+                "Expected {} values, but got {}",
+                types.len(),
+                idl_args.args.len()
+            );
+        }
+
+        let args = idl_args
+            .args
+            .iter()
+            .zip(types)
+            .map(|(value, ty)| {
+                let ty = unsafe { &**ty };
+
+                value
+                    .annotate_type(false, ty.env(), ty.ty())
+                    .map_err(AnyErr::from)
+            })
+            .collect::<AnyResult<Vec<_>>>()?;
+
+        Ok(IDLArgs { args })
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+/// Convert the values to a JSON array, in the way described in [`crate::candid::json`].
+#[no_mangle]
+pub extern "C" fn idl_args_to_json(
+    ptr: *const IDLArgs,
+    types: *const *const IDLType,
+    types_len: c_int,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_args = unsafe { &*ptr };
+    let types = unsafe { std::slice::from_raw_parts(types, types_len as usize) };
+    let types = types.iter().map(|ty| unsafe { &**ty }).collect::<Vec<_>>();
+
+    let json = json::args_to_json(idl_args, &types).map(|json| {
+        CString::new(json.to_string())
+            .unwrap()
+            .into_bytes_with_nul()
+    });
+
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, json)
+}
+
+/// Convert a JSON array to the values typed by `types`, in the way described in
+/// [`crate::candid::json`].
+#[no_mangle]
+pub extern "C" fn idl_args_from_json(
+    json: *const c_char,
+    types: *const *const IDLType,
+    types_len: c_int,
+    p2ptr: *mut *const IDLArgs,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let types = unsafe { std::slice::from_raw_parts(types, types_len as usize) };
+    let types = types.iter().map(|ty| unsafe { &**ty }).collect::<Vec<_>>();
+
+    let once = || -> AnyResult<IDLArgs> {
+        let json = unsafe { CStr::from_ptr(json).to_str().map_err(AnyErr::from)? };
+        let json = serde_json::from_str(json).map_err(AnyErr::from)?;
+
+        json::args_from_json(&json, &types)
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
 #[no_mangle]
 pub extern "C" fn idl_args_free(ptr: *const IDLArgs) {
     let boxed = unsafe { Box::from_raw(ptr as *mut IDLArgs) };
@@ -145,9 +234,14 @@ pub(crate) fn __todo_replace_this_by_macro(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::candid::idl_type::idl_type_free;
     use crate::tests_util::{apply_ptr, empty_err_cb};
+    use candid::parser::value::IDLField;
+    use candid::types::{Field, Label, Type};
+    use candid::{idl_hash, TypeEnv};
     use ic_types::Principal;
     use std::ops::Deref;
+    use std::sync::{Arc, Mutex};
 
     const IDL_VALUES: [IDLValue; 3] = [
         IDLValue::Bool(true),
@@ -260,6 +354,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop, clippy::ptr_offset_with_cast)]
     fn idl_args_as_vec_should_work() {
         extern "C" fn ret_cb(data: *const *const IDLValue, len: c_int) {
             for i in 0..len as usize {
@@ -280,4 +375,93 @@ mod tests {
 
         idl_args_free(ptr);
     }
+
+    #[test]
+    fn idl_args_annotate_should_work() {
+        let idl_args = IDLArgs::new(&[IDLValue::Record(vec![IDLField {
+            id: Label::Id(idl_hash("name")),
+            val: IDLValue::Text("ic".to_string()),
+        }])]);
+        let idl_type = IDLType::new(
+            Arc::new(TypeEnv::new()),
+            Type::Record(vec![Field {
+                id: Label::Named("name".to_string()),
+                ty: Type::Text,
+            }]),
+        );
+
+        let ptr = Box::into_raw(Box::new(idl_args));
+        let ptr_type = Box::into_raw(Box::new(idl_type)) as *const IDLType;
+        let mut ptr_annotated = apply_ptr::<IDLArgs>();
+
+        assert_eq!(
+            idl_args_annotate(ptr, &ptr_type, 1, &mut ptr_annotated, empty_err_cb),
+            StateCode::Ok
+        );
+
+        let annotated = unsafe { Box::from_raw(ptr_annotated as *mut IDLArgs) };
+        assert_eq!(annotated.to_string(), r#"(record { name = "ic" })"#);
+
+        assert_eq!(
+            idl_args_annotate(ptr, &ptr_type, 0, &mut ptr_annotated, empty_err_cb),
+            StateCode::Err
+        );
+
+        // Free here!
+        idl_args_free(ptr);
+        idl_type_free(ptr_type);
+    }
+
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn idl_args_json_should_work() {
+        static JSON: Mutex<String> = Mutex::new(String::new());
+
+        extern "C" fn ret_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const c_char) };
+
+            *JSON.lock().unwrap() = c_str.to_str().unwrap().to_string();
+        }
+
+        let idl_type = IDLType::new(
+            Arc::new(TypeEnv::new()),
+            Type::Record(vec![
+                Field {
+                    id: Label::Named("name".to_string()),
+                    ty: Type::Text,
+                },
+                Field {
+                    id: Label::Named("amount".to_string()),
+                    ty: Type::Nat64,
+                },
+            ]),
+        );
+        let ptr_type = Box::into_raw(Box::new(idl_type)) as *const IDLType;
+        let mut ptr = apply_ptr::<IDLArgs>();
+
+        assert_eq!(
+            idl_args_from_json(
+                "[{\"name\": \"ic\", \"amount\": 10}]\0".as_ptr() as *const c_char,
+                &ptr_type,
+                1,
+                &mut ptr,
+                empty_err_cb,
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(
+            idl_args_to_json(ptr, &ptr_type, 1, ret_cb, empty_err_cb),
+            StateCode::Ok
+        );
+        assert_eq!(*JSON.lock().unwrap(), r#"[{"amount":"10","name":"ic"}]"#);
+
+        assert_eq!(
+            idl_args_to_json(ptr, &ptr_type, 0, ret_cb, empty_err_cb),
+            StateCode::Err
+        );
+
+        // Free here!
+        idl_args_free(ptr);
+        idl_type_free(ptr_type);
+    }
 }
@@ -0,0 +1,602 @@
+use crate::{ret_thin_ptr, ret_unsized, AnyErr, AnyResult, StateCode, UnsizedCallBack};
+use anyhow::{anyhow, bail};
+use candid::parser::types::FuncMode;
+use candid::types::{Field, Label, Type};
+use candid::{check_prog, IDLProg, TypeEnv};
+use libc::c_char;
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// The kind of [`IDLType`], with the named types resolved.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IDLTypeKind {
+    Unknown = -1,
+    Null = 0,
+    Bool = 1,
+    Nat = 2,
+    Int = 3,
+    Nat8 = 4,
+    Nat16 = 5,
+    Nat32 = 6,
+    Nat64 = 7,
+    Int8 = 8,
+    Int16 = 9,
+    Int32 = 10,
+    Int64 = 11,
+    Float32 = 12,
+    Float64 = 13,
+    Text = 14,
+    Reserved = 15,
+    Empty = 16,
+    Principal = 17,
+    Opt = 18,
+    Vec = 19,
+    Record = 20,
+    Variant = 21,
+    Func = 22,
+    Service = 23,
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IDLFuncMode {
+    Update = 0,
+    Query = 1,
+    Oneway = 2,
+}
+
+/// A candid [`Type`] along with the [`TypeEnv`] in which its named types are defined.
+#[derive(Clone, Debug)]
+pub struct IDLType {
+    env: Arc<TypeEnv>,
+    ty: Type,
+}
+
+impl IDLType {
+    pub fn new(env: Arc<TypeEnv>, ty: Type) -> Self {
+        Self { env, ty }
+    }
+
+    /// Parse the type from text, e.g. `record { name : text }`. The named types in it are looked up
+    /// in the definitions of `did_content` if given.
+    pub fn from_text(text: &str, did_content: Option<&str>) -> AnyResult<Self> {
+        let mut env = TypeEnv::new();
+        if let Some(did_content) = did_content {
+            let ast = did_content.parse::<IDLProg>().map_err(AnyErr::from)?;
+            check_prog(&mut env, &ast).map_err(AnyErr::from)?;
+        }
+
+        let ast = text
+            .parse::<candid::parser::types::IDLType>()
+            .map_err(AnyErr::from)?;
+        let ty = env.ast_to_type(&ast).map_err(AnyErr::from)?;
+
+        Ok(Self::new(Arc::new(env), ty))
+    }
+
+    /// The service defined by `did_content`.
+    pub fn from_did(did_content: &str) -> AnyResult<Self> {
+        let ast = did_content.parse::<IDLProg>().map_err(AnyErr::from)?;
+
+        let mut env = TypeEnv::new();
+        match check_prog(&mut env, &ast).map_err(AnyErr::from)? {
+            Some(actor) => Ok(Self::new(Arc::new(env), actor)),
+            None => bail!("The candid file doesn't define a service"),
+        }
+    }
+
+    pub fn env(&self) -> &TypeEnv {
+        &self.env
+    }
+
+    pub fn ty(&self) -> &Type {
+        &self.ty
+    }
+
+    pub fn kind(&self) -> IDLTypeKind {
+        match self.trace() {
+            Ok(Type::Null) => IDLTypeKind::Null,
+            Ok(Type::Bool) => IDLTypeKind::Bool,
+            Ok(Type::Nat) => IDLTypeKind::Nat,
+            Ok(Type::Int) => IDLTypeKind::Int,
+            Ok(Type::Nat8) => IDLTypeKind::Nat8,
+            Ok(Type::Nat16) => IDLTypeKind::Nat16,
+            Ok(Type::Nat32) => IDLTypeKind::Nat32,
+            Ok(Type::Nat64) => IDLTypeKind::Nat64,
+            Ok(Type::Int8) => IDLTypeKind::Int8,
+            Ok(Type::Int16) => IDLTypeKind::Int16,
+            Ok(Type::Int32) => IDLTypeKind::Int32,
+            Ok(Type::Int64) => IDLTypeKind::Int64,
+            Ok(Type::Float32) => IDLTypeKind::Float32,
+            Ok(Type::Float64) => IDLTypeKind::Float64,
+            Ok(Type::Text) => IDLTypeKind::Text,
+            Ok(Type::Reserved) => IDLTypeKind::Reserved,
+            Ok(Type::Empty) => IDLTypeKind::Empty,
+            Ok(Type::Principal) => IDLTypeKind::Principal,
+            Ok(Type::Opt(_)) => IDLTypeKind::Opt,
+            Ok(Type::Vec(_)) => IDLTypeKind::Vec,
+            Ok(Type::Record(_)) => IDLTypeKind::Record,
+            Ok(Type::Variant(_)) => IDLTypeKind::Variant,
+            Ok(Type::Func(_)) => IDLTypeKind::Func,
+            Ok(Type::Service(_) | Type::Class(_, _)) => IDLTypeKind::Service,
+            _ => IDLTypeKind::Unknown,
+        }
+    }
+
+    /// The element type of `opt` or `vec`.
+    pub fn inner(&self) -> AnyResult<Self> {
+        match self.trace()? {
+            Type::Opt(ty) | Type::Vec(ty) => Ok(self.with(*ty)),
+            _ => bail!("The type {} is neither opt nor vec", self),
+        }
+    }
+
+    /// The labels and types of the fields of `record` or `variant`.
+    pub fn fields(&self) -> AnyResult<Vec<(String, Self)>> {
+        match self.trace()? {
+            Type::Record(fields) | Type::Variant(fields) => Ok(fields
+                .into_iter()
+                .map(|Field { id, ty }| {
+                    let id = match id {
+                        Label::Named(name) => name,
+                        id => id.get_id().to_string(),
+                    };
+
+                    (id, self.with(ty))
+                })
+                .collect()),
+            _ => bail!("The type {} is neither record nor variant", self),
+        }
+    }
+
+    /// The names and [`Type::Func`] of the methods of `service`.
+    pub fn methods(&self) -> AnyResult<Vec<(String, Self)>> {
+        let methods = self.env.as_service(&self.ty).map_err(AnyErr::from)?;
+
+        Ok(methods
+            .iter()
+            .map(|(name, ty)| (name.clone(), self.with(ty.clone())))
+            .collect())
+    }
+
+    /// The argument types, return types and mode of `func`.
+    pub fn func(&self) -> AnyResult<(Vec<Self>, Vec<Self>, IDLFuncMode)> {
+        let func = match self.trace()? {
+            Type::Func(func) => func,
+            _ => bail!("The type {} is not func", self),
+        };
+
+        let mode = match func.modes.first() {
+            Some(FuncMode::Query) => IDLFuncMode::Query,
+            Some(FuncMode::Oneway) => IDLFuncMode::Oneway,
+            None => IDLFuncMode::Update,
+        };
+
+        Ok((
+            func.args.into_iter().map(|ty| self.with(ty)).collect(),
+            func.rets.into_iter().map(|ty| self.with(ty)).collect(),
+            mode,
+        ))
+    }
+
+    /// Resolve the named type.
+    fn trace(&self) -> AnyResult<Type> {
+        self.env.trace_type(&self.ty).map_err(AnyErr::from)
+    }
+
+    fn with(&self, ty: Type) -> Self {
+        Self::new(self.env.clone(), ty)
+    }
+}
+
+impl Display for IDLType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.ty.fmt(f)
+    }
+}
+
+/// Give the handles of `types` to the caller, each of them has to be freed by [`idl_type_free`].
+pub(crate) fn ret_types(
+    ret_cb: UnsizedCallBack<*const IDLType>,
+    env: Arc<TypeEnv>,
+    types: &[Type],
+) {
+    let types = types.iter().map(|ty| IDLType::new(env.clone(), ty.clone()));
+
+    ret_handles(ret_cb, types);
+}
+
+fn ret_handles(ret_cb: UnsizedCallBack<*const IDLType>, types: impl IntoIterator<Item = IDLType>) {
+    let ptrs: Vec<*const IDLType> = types
+        .into_iter()
+        .map(|ty| Box::into_raw(Box::new(ty)) as *const IDLType)
+        .collect();
+
+    ret_unsized(ret_cb, ptrs);
+}
+
+/// Give the nul-terminated names to `names_cb` and the handles of types to `types_cb`.
+fn ret_named(
+    names_cb: UnsizedCallBack<*const u8>,
+    types_cb: UnsizedCallBack<*const IDLType>,
+    err_cb: UnsizedCallBack<u8>,
+    r: AnyResult<Vec<(String, IDLType)>>,
+) -> StateCode {
+    match r {
+        Ok(named) => {
+            let (names, types): (Vec<_>, Vec<_>) = named
+                .into_iter()
+                .map(|(name, ty)| (name + "\0", ty))
+                .unzip();
+
+            let name_ptrs: Vec<*const u8> = names.iter().map(|name| name.as_ptr()).collect();
+
+            ret_unsized(names_cb, name_ptrs);
+            ret_handles(types_cb, types);
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+/// Parse an [`IDLType`] from text, `did_content` can be null, or the candid file in which the
+/// named types are defined.
+#[no_mangle]
+pub extern "C" fn idl_type_from_text(
+    text: *const c_char,
+    did_content: *const c_char,
+    p2ptr: *mut *const IDLType,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<IDLType> {
+        let text = unsafe { CStr::from_ptr(text).to_str() }?;
+        let did_content = if did_content.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(did_content).to_str() }?)
+        };
+
+        IDLType::from_text(text, did_content)
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+/// Give the service defined by the candid file, of which the methods can be listed by
+/// [`idl_type_as_service`].
+#[no_mangle]
+pub extern "C" fn idl_type_from_did(
+    did_content: *const c_char,
+    p2ptr: *mut *const IDLType,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let did_content = unsafe { CStr::from_ptr(did_content).to_str().map_err(AnyErr::from) };
+
+    let idl_type = did_content.and_then(IDLType::from_did);
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, idl_type)
+}
+
+#[no_mangle]
+pub extern "C" fn idl_type_to_text(ptr: *const IDLType, ret_cb: UnsizedCallBack<u8>) {
+    let idl_type = unsafe { &*ptr };
+
+    ret_unsized(ret_cb, idl_type.to_string() + "\0");
+}
+
+#[no_mangle]
+pub extern "C" fn idl_type_kind(ptr: *const IDLType) -> IDLTypeKind {
+    let idl_type = unsafe { &*ptr };
+
+    idl_type.kind()
+}
+
+/// Give the element type of `opt`.
+#[no_mangle]
+pub extern "C" fn idl_type_as_opt(
+    ptr: *const IDLType,
+    p2ptr: *mut *const IDLType,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_type = unsafe { &*ptr };
+
+    let r = match idl_type.kind() {
+        IDLTypeKind::Opt => idl_type.inner(),
+        _ => Err(anyhow!("Not match the actual type")),
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, r)
+}
+
+/// Give the element type of `vec`.
+#[no_mangle]
+pub extern "C" fn idl_type_as_vec(
+    ptr: *const IDLType,
+    p2ptr: *mut *const IDLType,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_type = unsafe { &*ptr };
+
+    let r = match idl_type.kind() {
+        IDLTypeKind::Vec => idl_type.inner(),
+        _ => Err(anyhow!("Not match the actual type")),
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, r)
+}
+
+/// Give the labels of fields to `ids_cb`, and their types to `types_cb`.
+#[no_mangle]
+pub extern "C" fn idl_type_as_record(
+    ptr: *const IDLType,
+    ids_cb: UnsizedCallBack<*const u8>,
+    types_cb: UnsizedCallBack<*const IDLType>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_type = unsafe { &*ptr };
+
+    let r = match idl_type.kind() {
+        IDLTypeKind::Record => idl_type.fields(),
+        _ => Err(anyhow!("Not match the actual type")),
+    };
+
+    ret_named(ids_cb, types_cb, err_cb, r)
+}
+
+/// Give the labels of cases to `ids_cb`, and their types to `types_cb`.
+#[no_mangle]
+pub extern "C" fn idl_type_as_variant(
+    ptr: *const IDLType,
+    ids_cb: UnsizedCallBack<*const u8>,
+    types_cb: UnsizedCallBack<*const IDLType>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_type = unsafe { &*ptr };
+
+    let r = match idl_type.kind() {
+        IDLTypeKind::Variant => idl_type.fields(),
+        _ => Err(anyhow!("Not match the actual type")),
+    };
+
+    ret_named(ids_cb, types_cb, err_cb, r)
+}
+
+/// Give the names of methods to `names_cb`, and their `func` types to `funcs_cb`.
+#[no_mangle]
+pub extern "C" fn idl_type_as_service(
+    ptr: *const IDLType,
+    names_cb: UnsizedCallBack<*const u8>,
+    funcs_cb: UnsizedCallBack<*const IDLType>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_type = unsafe { &*ptr };
+
+    ret_named(names_cb, funcs_cb, err_cb, idl_type.methods())
+}
+
+/// Give the argument types to `args_cb`, the return types to `rets_cb`, and the mode to
+/// `ptr_mode`.
+#[no_mangle]
+pub extern "C" fn idl_type_as_func(
+    ptr: *const IDLType,
+    args_cb: UnsizedCallBack<*const IDLType>,
+    rets_cb: UnsizedCallBack<*const IDLType>,
+    ptr_mode: *mut IDLFuncMode,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let idl_type = unsafe { &*ptr };
+
+    match idl_type.func() {
+        Ok((args, rets, mode)) => {
+            ret_handles(args_cb, args);
+            ret_handles(rets_cb, rets);
+
+            unsafe {
+                *ptr_mode = mode;
+            }
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn idl_type_free(ptr: *const IDLType) {
+    let boxed = unsafe { Box::from_raw(ptr as *mut IDLType) };
+
+    drop(boxed);
+}
+
+pub(crate) fn __todo_replace_this_by_macro(
+    p2ptr: *mut *const IDLType,
+    err_cb: UnsizedCallBack<u8>,
+    r: Result<IDLType, impl Display>,
+) -> StateCode {
+    match r {
+        Ok(t) => {
+            unsafe {
+                ret_thin_ptr(p2ptr, t);
+            }
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests_util::{apply_ptr, empty_err_cb};
+    use libc::c_int;
+    use std::sync::Mutex;
+
+    const II_DID_CONTENT: &str = include_str!("../agent/rdmx6-jaaaa-aaaaa-aaadq-cai.did");
+
+    const RECORD_TEXT: &str = "record { name : text; avatar : opt vec nat8; 42 : bool }\0";
+
+    static NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static TYPES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    extern "C" fn names_cb(data: *const *const u8, len: c_int) {
+        let ptrs = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+        *NAMES.lock().unwrap() = ptrs
+            .iter()
+            .map(|ptr| {
+                let c_str = unsafe { CStr::from_ptr(*ptr as *const c_char) };
+                c_str.to_str().unwrap().to_string()
+            })
+            .collect();
+    }
+
+    extern "C" fn types_cb(data: *const *const IDLType, len: c_int) {
+        let ptrs = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+        *TYPES.lock().unwrap() = ptrs
+            .iter()
+            .map(|ptr| {
+                let idl_type = unsafe { Box::from_raw(*ptr as *mut IDLType) };
+                idl_type.to_string()
+            })
+            .collect();
+    }
+
+    #[test]
+    fn idl_type_to_text_should_work() {
+        extern "C" fn ret_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const i8) };
+
+            assert_eq!(c_str.to_str().unwrap(), "vec nat8");
+        }
+
+        let idl_type = IDLType::new(Arc::new(TypeEnv::new()), Type::Vec(Box::new(Type::Nat8)));
+        let ptr = Box::into_raw(Box::new(idl_type));
+
+        idl_type_to_text(ptr, ret_cb);
+
+        // Free here!
+        idl_type_free(ptr);
+    }
+
+    #[test]
+    fn idl_type_from_text_should_work() {
+        let mut ptr = apply_ptr::<IDLType>();
+
+        assert_eq!(
+            idl_type_from_text(
+                RECORD_TEXT.as_ptr() as *const c_char,
+                std::ptr::null(),
+                &mut ptr,
+                empty_err_cb
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(idl_type_kind(ptr), IDLTypeKind::Record);
+
+        let idl_type = unsafe { &*ptr };
+        let fields = idl_type.fields().unwrap();
+        let labels: Vec<_> = fields.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, vec!["42", "name", "avatar"]);
+
+        let avatar = fields[2].1.inner().unwrap();
+        assert_eq!(avatar.kind(), IDLTypeKind::Vec);
+        assert_eq!(avatar.inner().unwrap().kind(), IDLTypeKind::Nat8);
+
+        let mut ptr_inner = apply_ptr::<IDLType>();
+        assert_eq!(
+            idl_type_as_opt(ptr, &mut ptr_inner, empty_err_cb),
+            StateCode::Err
+        );
+
+        let text = b"DeviceData\0";
+        let did_content = II_DID_CONTENT.to_string() + "\0";
+        let mut ptr_device = apply_ptr::<IDLType>();
+        assert_eq!(
+            idl_type_from_text(
+                text.as_ptr() as *const c_char,
+                did_content.as_ptr() as *const c_char,
+                &mut ptr_device,
+                empty_err_cb
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(idl_type_kind(ptr_device), IDLTypeKind::Record);
+
+        assert_eq!(
+            idl_type_from_text(
+                text.as_ptr() as *const c_char,
+                std::ptr::null(),
+                &mut ptr_inner,
+                empty_err_cb
+            ),
+            StateCode::Err
+        );
+
+        // Free here!
+        idl_type_free(ptr);
+        idl_type_free(ptr_device);
+    }
+
+    #[test]
+    fn idl_type_as_service_should_work() {
+        let did_content = II_DID_CONTENT.to_string() + "\0";
+        let mut ptr = apply_ptr::<IDLType>();
+
+        assert_eq!(
+            idl_type_from_did(
+                did_content.as_ptr() as *const c_char,
+                &mut ptr,
+                empty_err_cb
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(idl_type_kind(ptr), IDLTypeKind::Service);
+
+        assert_eq!(
+            idl_type_as_service(ptr, names_cb, types_cb, empty_err_cb),
+            StateCode::Ok
+        );
+        let names = NAMES.lock().unwrap().clone();
+        let lookup = names.iter().position(|name| name == "lookup").unwrap();
+        assert_eq!(
+            TYPES.lock().unwrap()[lookup],
+            "func (UserNumber) -> (vec DeviceData) query"
+        );
+
+        let idl_type = unsafe { &*ptr };
+        let (_, lookup) = &idl_type.methods().unwrap()[lookup];
+        let (args, rets, mode) = lookup.func().unwrap();
+        assert_eq!(args[0].kind(), IDLTypeKind::Nat64);
+        assert_eq!(rets[0].kind(), IDLTypeKind::Vec);
+        assert_eq!(mode, IDLFuncMode::Query);
+
+        let device_data = rets[0].inner().unwrap();
+        let ptr_device = Box::into_raw(Box::new(device_data));
+        assert_eq!(
+            idl_type_as_record(ptr_device, names_cb, types_cb, empty_err_cb),
+            StateCode::Ok
+        );
+        assert!(NAMES.lock().unwrap().contains(&"credential_id".to_string()));
+
+        // Free here!
+        idl_type_free(ptr);
+        idl_type_free(ptr_device);
+    }
+}
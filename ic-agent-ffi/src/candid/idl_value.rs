@@ -195,6 +195,40 @@ pub extern "C" fn idl_value_ct_record(
     __todo_replace_this_by_macro_unsized(p2ptr, err_cb, once())
 }
 
+/// Like [`idl_value_ct_record`], but the fields are labelled by their ids rather than names, e.g.
+/// the fields of a tuple or the hashed ones.
+#[no_mangle]
+pub extern "C" fn idl_value_ct_record_with_ids(
+    ids: *const u32,
+    ids_len: c_int,
+    vals: *const *const IDLValue,
+    vals_len: c_int,
+    p2ptr: *mut *const IDLValue,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || {
+        if ids_len != vals_len {
+            return Err(anyhow!("The length of ids and vals are not matched"));
+        }
+
+        let ids = unsafe { std::slice::from_raw_parts(ids, ids_len as usize) };
+        let vals = unsafe { std::slice::from_raw_parts(vals, vals_len as usize) };
+
+        let fields = ids
+            .iter()
+            .zip(vals)
+            .map(|(id, val)| IDLField {
+                id: Label::Id(*id),
+                val: unsafe { &**val }.clone(),
+            })
+            .collect();
+
+        Ok(IDLValue::Record(fields))
+    };
+
+    __todo_replace_this_by_macro_unsized(p2ptr, err_cb, once())
+}
+
 #[no_mangle]
 pub extern "C" fn idl_value_ct_variant(
     key: *const c_char,
@@ -1207,6 +1241,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn idl_value_ct_record_should_work() {
         const KEYS: &[*const c_char] = &[
             b"Arg01\0".as_ptr() as *const c_char,
@@ -1252,6 +1287,52 @@ mod tests {
     }
 
     #[test]
+    fn idl_value_ct_record_with_ids_should_work() {
+        const IDS: &[u32] = &[0, 1];
+        const VALS: &[*const IDLValue] = &[&IDLValue::Bool(true), &IDLValue::Null];
+
+        let expected = IDLValue::Record(vec![
+            IDLField {
+                id: Label::Unnamed(0),
+                val: IDLValue::Bool(true),
+            },
+            IDLField {
+                id: Label::Unnamed(1),
+                val: IDLValue::Null,
+            },
+        ]);
+
+        let mut ptr = apply_ptr::<IDLValue>();
+        assert_eq!(
+            idl_value_ct_record_with_ids(
+                IDS.as_ptr(),
+                IDS.len() as c_int,
+                VALS.as_ptr(),
+                VALS.len() as c_int,
+                &mut ptr,
+                empty_err_cb,
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(
+            idl_value_ct_record_with_ids(
+                IDS.as_ptr(),
+                IDS.len() as c_int,
+                VALS.as_ptr(),
+                1,
+                &mut ptr,
+                empty_err_cb,
+            ),
+            StateCode::Err
+        );
+
+        // Free here!
+        let boxed = unsafe { Box::from_raw(ptr as *mut IDLValue) };
+        assert_eq!(&expected, boxed.deref());
+    }
+
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn idl_value_ct_variant_should_work() {
         const KEY: *const c_char = b"Variant\0".as_ptr() as *const c_char;
         const VAL: *const IDLValue = &IDLValue::Bool(true);
@@ -1311,6 +1392,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn idl_value_ct_func_should_work() {
         const PRINCIPAL: Principal = Principal::anonymous();
         const FUNC_NAME: &str = "hello_word";
@@ -1340,6 +1422,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn idl_value_ct_int_should_work() {
         const INT: &str = "12345678901234567890";
         const P_INT: *const c_char = b"12345678901234567890\0".as_ptr() as *const c_char;
@@ -1354,6 +1437,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::manual_c_str_literals)]
     fn idl_value_ct_nat_should_work() {
         const NAT: &str = "12345678901234567890";
         const P_NAT: *const c_char = b"12345678901234567890\0".as_ptr() as *const c_char;
@@ -1448,6 +1532,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn idl_value_ct_float32_should_work() {
         const FLOAT: f32 = 0.123456789;
 
@@ -1505,6 +1590,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn idl_value_equal_should_work() {
         let boxed_01 = Box::new(IDLValue::Bool(true));
         let boxed_02 = Box::new(IDLValue::Bool(false));
@@ -1532,6 +1618,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn idl_value_as_bool_should_work() {
         const IDL_VALUE: IDLValue = IDLValue::Bool(true);
 
@@ -1647,6 +1734,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop, clippy::ptr_offset_with_cast)]
     fn idl_value_as_vec_should_work() {
         const IDL_VALUE_LIST: [IDLValue; 3] = [
             IDLValue::Bool(true),
@@ -1673,6 +1761,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop, clippy::ptr_offset_with_cast)]
     fn idl_value_as_record_should_work() {
         const KEYS: [&str; 3] = ["Key01", "123", "Key03"];
         const VALS: [IDLValue; 3] = [
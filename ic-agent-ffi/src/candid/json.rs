@@ -0,0 +1,463 @@
+//! Convert between candid values and JSON, guided by the candid types:
+//!
+//! * `nat`, `int`, `nat64` and `int64` are strings in JSON, the other numbers are numbers.
+//! * `blob` is a hex string prefixed by `0x`, any other string is read as base64.
+//! * `opt` is `null` or the inner value, so `opt opt T` can't tell `null` from `opt null`: both
+//!   are written as `null`, which is read back as `null`.
+//! * `record` is an object, or an array if it's a tuple.
+//! * `variant` is `{"tag": value}`, a lone `"tag"` is also read as `{"tag": null}` but never
+//!   written.
+//! * `principal` and `service` are the texts, `func` is `[principal, method]`.
+
+use crate::candid::idl_type::IDLType;
+use crate::{AnyErr, AnyResult};
+use anyhow::{anyhow, bail};
+use candid::parser::value::{IDLField, IDLValue, VariantValue};
+use candid::types::{Field, Label, Type};
+use candid::{IDLArgs, Int, Nat, TypeEnv};
+use ic_types::Principal;
+use serde_json::{Map, Number, Value};
+use std::str::FromStr;
+
+/// Convert `args` to a JSON array, of which the elements are typed by `types`.
+pub fn args_to_json(args: &IDLArgs, types: &[&IDLType]) -> AnyResult<Value> {
+    if args.args.len() != types.len() {
+        bail!(
+            "Expected {} values, but got {}",
+            types.len(),
+            args.args.len()
+        );
+    }
+
+    let values = args
+        .args
+        .iter()
+        .zip(types)
+        .map(|(value, ty)| to_json(value, ty.env(), ty.ty()))
+        .collect::<AnyResult<_>>()?;
+
+    Ok(Value::Array(values))
+}
+
+/// Convert a JSON array to [`IDLArgs`], of which the elements are typed by `types`.
+pub fn args_from_json(json: &Value, types: &[&IDLType]) -> AnyResult<IDLArgs> {
+    let values = match json {
+        Value::Array(values) if values.len() == types.len() => values,
+        _ => bail!("Expected an array of {} values", types.len()),
+    };
+
+    let args = values
+        .iter()
+        .zip(types)
+        .map(|(value, ty)| from_json(value, ty.env(), ty.ty()))
+        .collect::<AnyResult<_>>()?;
+
+    Ok(IDLArgs { args })
+}
+
+pub fn to_json(value: &IDLValue, env: &TypeEnv, ty: &Type) -> AnyResult<Value> {
+    let ty = env.trace_type(ty).map_err(AnyErr::from)?;
+
+    let json = match (value, &ty) {
+        (_, Type::Reserved) | (IDLValue::Null, Type::Null) => Value::Null,
+        (IDLValue::Bool(b), Type::Bool) => Value::Bool(*b),
+        (IDLValue::Text(text), Type::Text) => Value::String(text.clone()),
+        (IDLValue::Float64(f), Type::Float64 | Type::Float32) => float_to_json(*f)?,
+        (IDLValue::Float32(f), Type::Float32) => float_to_json(*f as f64)?,
+        (_, Type::Nat | Type::Int | Type::Nat64 | Type::Int64) => {
+            Value::String(number_text(value).ok_or_else(|| mismatch(value, &ty))?)
+        }
+        (_, Type::Nat8 | Type::Nat16 | Type::Nat32 | Type::Int8 | Type::Int16 | Type::Int32) => {
+            let text = number_text(value).ok_or_else(|| mismatch(value, &ty))?;
+
+            Value::Number(Number::from_str(&text).map_err(AnyErr::from)?)
+        }
+        (IDLValue::None | IDLValue::Null, Type::Opt(_)) => Value::Null,
+        (IDLValue::Opt(value), Type::Opt(inner)) => to_json(value, env, inner)?,
+        (IDLValue::Vec(values), Type::Vec(inner)) => {
+            if matches!(env.trace_type(inner).map_err(AnyErr::from)?, Type::Nat8) {
+                let bytes = values
+                    .iter()
+                    .map(|value| match value {
+                        IDLValue::Nat8(byte) => Ok(*byte),
+                        value => u8::from_str(&number_text(value).unwrap_or_default())
+                            .map_err(|_| mismatch(value, &Type::Nat8)),
+                    })
+                    .collect::<AnyResult<Vec<_>>>()?;
+
+                Value::String(format!("0x{}", hex::encode(bytes)))
+            } else {
+                Value::Array(
+                    values
+                        .iter()
+                        .map(|value| to_json(value, env, inner))
+                        .collect::<AnyResult<_>>()?,
+                )
+            }
+        }
+        (IDLValue::Record(values), Type::Record(fields)) => {
+            let mut entries = Vec::new();
+            for Field { id, ty } in fields {
+                let json = match values.iter().find(|field| field.id == *id) {
+                    Some(field) => to_json(&field.val, env, ty)?,
+                    None => to_json(&IDLValue::None, env, ty)?,
+                };
+
+                entries.push((id, json));
+            }
+
+            if is_tuple(fields) {
+                Value::Array(entries.into_iter().map(|(_, json)| json).collect())
+            } else {
+                Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(id, json)| (label_key(id), json))
+                        .collect(),
+                )
+            }
+        }
+        (IDLValue::Variant(VariantValue(value, _)), Type::Variant(fields)) => {
+            let Field { id, ty } = fields
+                .iter()
+                .find(|field| field.id == value.id)
+                .ok_or_else(|| anyhow!("The variant has no case {}", value.id))?;
+
+            let mut object = Map::new();
+            object.insert(label_key(id), to_json(&value.val, env, ty)?);
+
+            Value::Object(object)
+        }
+        (IDLValue::Principal(principal), Type::Principal)
+        | (IDLValue::Service(principal), Type::Service(_)) => Value::String(principal.to_text()),
+        (IDLValue::Func(principal, method), Type::Func(_)) => Value::Array(vec![
+            Value::String(principal.to_text()),
+            Value::String(method.clone()),
+        ]),
+        // The value of `opt` is allowed to omit the wrapper.
+        (value, Type::Opt(inner)) => to_json(value, env, inner)?,
+        (value, ty) => return Err(mismatch(value, ty)),
+    };
+
+    Ok(json)
+}
+
+pub fn from_json(json: &Value, env: &TypeEnv, ty: &Type) -> AnyResult<IDLValue> {
+    let ty = env.trace_type(ty).map_err(AnyErr::from)?;
+
+    let value = match (json, &ty) {
+        (_, Type::Reserved) => IDLValue::Reserved,
+        (Value::Null, Type::Null) => IDLValue::Null,
+        (Value::Bool(b), Type::Bool) => IDLValue::Bool(*b),
+        (Value::String(text), Type::Text) => IDLValue::Text(text.clone()),
+        (Value::Number(n), Type::Float64) => IDLValue::Float64(json_f64(n)?),
+        (Value::Number(n), Type::Float32) => IDLValue::Float32(json_f64(n)? as f32),
+        (Value::Number(_) | Value::String(_), _) if is_integer(&ty) => {
+            let text = match json {
+                Value::String(text) => text.replace('_', ""),
+                json => json.to_string(),
+            };
+
+            integer_from_text(&text, &ty)?
+        }
+        (Value::Null, Type::Opt(_)) => IDLValue::None,
+        (json, Type::Opt(inner)) => IDLValue::Opt(Box::new(from_json(json, env, inner)?)),
+        (Value::String(text), Type::Vec(inner))
+            if matches!(env.trace_type(inner).map_err(AnyErr::from)?, Type::Nat8) =>
+        {
+            let bytes = match text.strip_prefix("0x") {
+                Some(hex) => hex::decode(hex).map_err(|_| anyhow!("The blob is not hex"))?,
+                None => base64::decode(text).map_err(|_| anyhow!("The blob is not base64"))?,
+            };
+
+            IDLValue::Vec(bytes.into_iter().map(IDLValue::Nat8).collect())
+        }
+        (Value::Array(values), Type::Vec(inner)) => IDLValue::Vec(
+            values
+                .iter()
+                .map(|value| from_json(value, env, inner))
+                .collect::<AnyResult<_>>()?,
+        ),
+        (Value::Array(values), Type::Record(fields)) if is_tuple(fields) => {
+            if values.len() != fields.len() {
+                bail!("Expected a tuple of {} values", fields.len());
+            }
+
+            IDLValue::Record(
+                values
+                    .iter()
+                    .zip(fields)
+                    .map(|(value, Field { id, ty })| {
+                        Ok(IDLField {
+                            id: id.clone(),
+                            val: from_json(value, env, ty)?,
+                        })
+                    })
+                    .collect::<AnyResult<_>>()?,
+            )
+        }
+        (Value::Object(object), Type::Record(fields)) => IDLValue::Record(
+            fields
+                .iter()
+                .map(|Field { id, ty }| {
+                    let value = object.get(&label_key(id)).unwrap_or(&Value::Null);
+
+                    Ok(IDLField {
+                        id: id.clone(),
+                        val: from_json(value, env, ty)
+                            .map_err(|e| anyhow!("The field {} is invalid: {}", id, e))?,
+                    })
+                })
+                .collect::<AnyResult<_>>()?,
+        ),
+        (Value::String(tag), Type::Variant(fields)) => {
+            variant_from_json(tag, &Value::Null, env, fields)?
+        }
+        (Value::Object(object), Type::Variant(fields)) if object.len() == 1 => {
+            let (tag, value) = object.iter().next().unwrap();
+
+            variant_from_json(tag, value, env, fields)?
+        }
+        (Value::String(text), Type::Principal) => {
+            IDLValue::Principal(Principal::from_text(text).map_err(AnyErr::from)?)
+        }
+        (Value::String(text), Type::Service(_)) => {
+            IDLValue::Service(Principal::from_text(text).map_err(AnyErr::from)?)
+        }
+        (Value::Array(values), Type::Func(_)) => match values.as_slice() {
+            [Value::String(principal), Value::String(method)] => IDLValue::Func(
+                Principal::from_text(principal).map_err(AnyErr::from)?,
+                method.clone(),
+            ),
+            _ => bail!("Expected [principal, method] of func"),
+        },
+        (json, ty) => bail!("The JSON {} doesn't match the type {}", json, ty),
+    };
+
+    Ok(value)
+}
+
+fn variant_from_json(
+    tag: &str,
+    value: &Value,
+    env: &TypeEnv,
+    fields: &[Field],
+) -> AnyResult<IDLValue> {
+    let (index, Field { id, ty }) = fields
+        .iter()
+        .enumerate()
+        .find(|(_, field)| label_key(&field.id) == tag)
+        .ok_or_else(|| anyhow!("The variant has no case {}", tag))?;
+
+    let field = IDLField {
+        id: id.clone(),
+        val: from_json(value, env, ty)?,
+    };
+
+    Ok(IDLValue::Variant(VariantValue(
+        Box::new(field),
+        index as u64,
+    )))
+}
+
+fn integer_from_text(text: &str, ty: &Type) -> AnyResult<IDLValue> {
+    let invalid = || anyhow!("The number {} is not a valid {}", text, ty);
+
+    let value = match ty {
+        Type::Nat => IDLValue::Nat(Nat::from_str(text).map_err(|_| invalid())?),
+        Type::Int => IDLValue::Int(Int::from_str(text).map_err(|_| invalid())?),
+        Type::Nat8 => IDLValue::Nat8(text.parse().map_err(|_| invalid())?),
+        Type::Nat16 => IDLValue::Nat16(text.parse().map_err(|_| invalid())?),
+        Type::Nat32 => IDLValue::Nat32(text.parse().map_err(|_| invalid())?),
+        Type::Nat64 => IDLValue::Nat64(text.parse().map_err(|_| invalid())?),
+        Type::Int8 => IDLValue::Int8(text.parse().map_err(|_| invalid())?),
+        Type::Int16 => IDLValue::Int16(text.parse().map_err(|_| invalid())?),
+        Type::Int32 => IDLValue::Int32(text.parse().map_err(|_| invalid())?),
+        Type::Int64 => IDLValue::Int64(text.parse().map_err(|_| invalid())?),
+        ty => bail!("The type {} is not an integer", ty),
+    };
+
+    Ok(value)
+}
+
+fn is_integer(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Nat
+            | Type::Int
+            | Type::Nat8
+            | Type::Nat16
+            | Type::Nat32
+            | Type::Nat64
+            | Type::Int8
+            | Type::Int16
+            | Type::Int32
+            | Type::Int64
+    )
+}
+
+/// The decimal text of an integer value, without the `_` separators.
+fn number_text(value: &IDLValue) -> Option<String> {
+    let text = match value {
+        IDLValue::Number(text) => text.replace('_', ""),
+        IDLValue::Nat(n) => n.0.to_string(),
+        IDLValue::Int(i) => i.0.to_string(),
+        IDLValue::Nat8(n) => n.to_string(),
+        IDLValue::Nat16(n) => n.to_string(),
+        IDLValue::Nat32(n) => n.to_string(),
+        IDLValue::Nat64(n) => n.to_string(),
+        IDLValue::Int8(i) => i.to_string(),
+        IDLValue::Int16(i) => i.to_string(),
+        IDLValue::Int32(i) => i.to_string(),
+        IDLValue::Int64(i) => i.to_string(),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+fn float_to_json(f: f64) -> AnyResult<Value> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| anyhow!("The float {} can't be in JSON", f))
+}
+
+fn json_f64(n: &Number) -> AnyResult<f64> {
+    n.as_f64()
+        .ok_or_else(|| anyhow!("The number {} is not a float", n))
+}
+
+/// The key of field in JSON object, the name or the decimal id.
+fn label_key(label: &Label) -> String {
+    match label {
+        Label::Named(name) => name.clone(),
+        label => label.get_id().to_string(),
+    }
+}
+
+fn is_tuple(fields: &[Field]) -> bool {
+    !fields.is_empty()
+        && fields
+            .iter()
+            .enumerate()
+            .all(|(i, field)| matches!(field.id, Label::Unnamed(n) if n as usize == i))
+}
+
+fn mismatch(value: &IDLValue, ty: &Type) -> AnyErr {
+    anyhow!("The value {} doesn't match the type {}", value, ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DID_CONTENT: &str = r#"
+type Purpose = variant { recovery; authentication };
+type Device = record {
+  pubkey : blob;
+  alias : text;
+  credential_id : opt blob;
+  purpose : Purpose;
+  expiry : nat64;
+  weight : nat8;
+  balance : int;
+  header : record { text; text };
+};
+service : {}
+"#;
+
+    fn device_type() -> IDLType {
+        IDLType::from_text("Device", Some(DID_CONTENT)).unwrap()
+    }
+
+    #[test]
+    fn args_json_should_work() {
+        let ty = device_type();
+        let json: Value = serde_json::from_str(
+            r#"[{
+                "pubkey": "0x0102ff",
+                "alias": "laptop",
+                "credential_id": null,
+                "purpose": { "authentication": null },
+                "expiry": "18446744073709551615",
+                "weight": 7,
+                "balance": "-12345678901234567890",
+                "header": ["Content-Type", "text/plain"]
+            }]"#,
+        )
+        .unwrap();
+
+        let args = args_from_json(&json, &[&ty]).unwrap();
+        assert_eq!(
+            args.to_string(),
+            "(\n  record {\n    \
+            weight = 7 : nat8;\n    \
+            alias = \"laptop\";\n    \
+            balance = -12_345_678_901_234_567_890 : int;\n    \
+            pubkey = blob \"\\01\\02\\ff\";\n    \
+            expiry = 18_446_744_073_709_551_615 : nat64;\n    \
+            purpose = variant { authentication };\n    \
+            credential_id = null;\n    \
+            header = record { \"Content-Type\"; \"text/plain\" };\n  \
+            },\n)"
+        );
+
+        assert_eq!(args_to_json(&args, &[&ty]).unwrap(), json);
+    }
+
+    #[test]
+    fn from_json_should_work() {
+        let env = TypeEnv::new();
+        let blob = Type::Vec(Box::new(Type::Nat8));
+
+        assert_eq!(
+            from_json(&Value::String("AQL/".to_string()), &env, &blob).unwrap(),
+            IDLValue::Vec(vec![
+                IDLValue::Nat8(1),
+                IDLValue::Nat8(2),
+                IDLValue::Nat8(255)
+            ])
+        );
+        // Also valid hex, but it's base64 without the prefix
+        assert_eq!(
+            from_json(&Value::String("AAAA".to_string()), &env, &blob).unwrap(),
+            IDLValue::Vec(vec![IDLValue::Nat8(0); 3])
+        );
+        assert_eq!(
+            from_json(&Value::String("0xAAAA".to_string()), &env, &blob).unwrap(),
+            IDLValue::Vec(vec![IDLValue::Nat8(0xaa); 2])
+        );
+        assert_eq!(
+            from_json(&serde_json::json!(12), &env, &Type::Nat).unwrap(),
+            IDLValue::Nat(Nat::from_str("12").unwrap())
+        );
+
+        let purpose = device_type().fields().unwrap();
+        let (_, purpose) = purpose.iter().find(|(id, _)| id == "purpose").unwrap();
+        assert!(matches!(
+            from_json(&serde_json::json!("recovery"), purpose.env(), purpose.ty()).unwrap(),
+            IDLValue::Variant(_)
+        ));
+    }
+
+    #[test]
+    fn from_json_should_fail() {
+        let env = TypeEnv::new();
+        let ty = device_type();
+
+        assert!(from_json(&serde_json::json!(256), &env, &Type::Nat8).is_err());
+        assert!(from_json(&serde_json::json!(-1), &env, &Type::Nat).is_err());
+        assert!(from_json(&serde_json::json!("xyz"), &env, &Type::Principal).is_err());
+        assert!(from_json(&serde_json::json!({}), ty.env(), ty.ty()).is_err());
+        assert!(from_json(
+            &serde_json::json!("0xAQL/"),
+            &env,
+            &Type::Vec(Box::new(Type::Nat8))
+        )
+        .is_err());
+        assert!(args_from_json(&serde_json::json!([]), &[&ty]).is_err());
+
+        let variant = Type::Variant(Vec::new());
+        assert!(from_json(&serde_json::json!({"a": 1, "b": 2}), &env, &variant).is_err());
+    }
+}
@@ -1,2 +1,4 @@
 pub mod idl_args;
+pub mod idl_type;
 pub mod idl_value;
+pub mod json;
@@ -0,0 +1,724 @@
+use crate::{AnyErr, AnyResult};
+use candid::parser::types::FuncMode;
+use candid::types::{Field, Function, Label, Type};
+use candid::TypeEnv;
+use std::fmt::Write;
+
+const HEADER: &str = "// Generated from candid by ic-agent-ffi, do not edit.
+#nullable enable
+using System;
+using System.Collections.Generic;
+using System.Linq;
+using System.Numerics;
+using Candid;
+";
+
+const KEYWORDS: &[&str] = &[
+    "abstract",
+    "as",
+    "base",
+    "bool",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "checked",
+    "class",
+    "const",
+    "continue",
+    "decimal",
+    "default",
+    "delegate",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "event",
+    "explicit",
+    "extern",
+    "false",
+    "finally",
+    "fixed",
+    "float",
+    "for",
+    "foreach",
+    "goto",
+    "if",
+    "implicit",
+    "in",
+    "int",
+    "interface",
+    "internal",
+    "is",
+    "lock",
+    "long",
+    "namespace",
+    "new",
+    "null",
+    "object",
+    "operator",
+    "out",
+    "override",
+    "params",
+    "private",
+    "protected",
+    "public",
+    "readonly",
+    "ref",
+    "return",
+    "sbyte",
+    "sealed",
+    "short",
+    "sizeof",
+    "stackalloc",
+    "static",
+    "string",
+    "struct",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "uint",
+    "ulong",
+    "unchecked",
+    "unsafe",
+    "ushort",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+];
+
+/// How a candid type is represented in C#, and converted from and to `IDLValue`.
+#[derive(Clone, Debug)]
+enum CsType {
+    /// The C# type, whether it's a value type, and the methods of `IDLValue` to convert it.
+    Prim(&'static str, bool, &'static str, &'static str),
+    Func,
+    Opt(Box<CsType>),
+    Vec(Box<CsType>),
+    /// A generated class of record or variant.
+    Class(String),
+    /// Left as `IDLValue`, for the types without a natural C# counterpart.
+    Raw,
+}
+
+impl CsType {
+    fn name(&self) -> String {
+        match self {
+            Self::Prim(name, ..) => name.to_string(),
+            Self::Func => "(Principal, string)".to_string(),
+            Self::Opt(inner) => format!("{}?", inner.name()),
+            Self::Vec(inner) => format!("{}[]", inner.name()),
+            Self::Class(name) => name.clone(),
+            Self::Raw => "IDLValue".to_string(),
+        }
+    }
+
+    fn is_value_type(&self) -> bool {
+        matches!(self, Self::Prim(_, true, ..) | Self::Func)
+    }
+
+    /// The C# expression converting `expr` of this type to `IDLValue`.
+    fn encode(&self, expr: &str, depth: usize) -> String {
+        match self {
+            Self::Prim(_, _, with, _) => format!("IDLValue.{with}({expr})"),
+            Self::Func => format!("IDLValue.WithFunc({expr}.Item1, {expr}.Item2)"),
+            Self::Opt(inner) => {
+                let unwrapped = if inner.is_value_type() {
+                    format!("{expr}.Value")
+                } else {
+                    format!("{expr}!")
+                };
+
+                format!(
+                    "({expr} == null ? IDLValue.WithNone() : IDLValue.WithOpt({}))",
+                    inner.encode(&unwrapped, depth)
+                )
+            }
+            Self::Vec(inner) => {
+                let elem = format!("e{depth}");
+
+                format!(
+                    "IDLValue.WithVec({expr}.Select({elem} => {}).ToArray())",
+                    inner.encode(&elem, depth + 1)
+                )
+            }
+            Self::Class(_) => format!("{expr}.ToIDL()"),
+            Self::Raw => expr.to_string(),
+        }
+    }
+
+    /// The C# expression converting `expr` of `IDLValue` to this type.
+    fn decode(&self, expr: &str, depth: usize) -> String {
+        match self {
+            Self::Prim(_, _, _, as_) => format!("{expr}.{as_}()"),
+            Self::Func => format!("{expr}.AsFunc()"),
+            Self::Opt(inner) => format!(
+                "({expr}.IsNone() ? ({})null : {})",
+                self.name(),
+                inner.decode(&format!("{expr}.AsOpt()"), depth)
+            ),
+            Self::Vec(inner) => {
+                let elem = format!("e{depth}");
+
+                format!(
+                    "{expr}.AsVec().Select({elem} => {}).ToArray()",
+                    inner.decode(&elem, depth + 1)
+                )
+            }
+            Self::Class(name) => format!("{name}.FromIDL({expr})"),
+            Self::Raw => expr.to_string(),
+        }
+    }
+}
+
+/// Generates the C# classes of the records and variants, and an actor class of the service.
+pub struct CSharpGenerator<'a> {
+    env: &'a TypeEnv,
+    actor_name: String,
+    classes: Vec<(String, Type)>,
+}
+
+impl<'a> CSharpGenerator<'a> {
+    pub fn new(env: &'a TypeEnv, actor_name: &str) -> Self {
+        Self {
+            env,
+            actor_name: actor_name.to_string(),
+            classes: Vec::new(),
+        }
+    }
+
+    /// Generate the source of `actor`, which is the service given by `check_prog`.
+    pub fn generate(mut self, actor: &Type) -> AnyResult<String> {
+        let methods = self.env.as_service(actor).map_err(AnyErr::from)?;
+
+        let mut body = String::new();
+        for (name, ty) in methods {
+            let func = self.env.as_func(ty).map_err(AnyErr::from)?;
+
+            body += &self.method(name, func)?;
+        }
+
+        let mut src = HEADER.to_string();
+        writeln!(
+            src,
+            "
+public class {actor}
+{{
+    private readonly Agent _agent;
+
+    public {actor}(Agent agent)
+    {{
+        _agent = agent;
+    }}
+{body}
+    private IDLValue[] CallCandid(string funcName, IDLArgs args, bool query)
+    {{
+        var reply = query ? _agent.Query(funcName, args) : _agent.Update(funcName, args);
+        var (_, rets) = _agent.MethodTypes(funcName);
+
+        return reply.Annotate(rets).AsVec();
+    }}
+}}",
+            actor = self.actor_name,
+        )?;
+
+        // The classes may refer to more classes, which are appended while generating.
+        let mut i = 0;
+        while i < self.classes.len() {
+            let (name, ty) = self.classes[i].clone();
+
+            src += &match ty {
+                Type::Record(fields) => self.record(&name, &fields)?,
+                Type::Variant(fields) => self.variant(&name, &fields)?,
+                _ => unreachable!("Only the records and variants are generated as classes"),
+            };
+            i += 1;
+        }
+
+        src += "#nullable disable\n";
+
+        Ok(src)
+    }
+
+    fn method(&mut self, name: &str, func: &Function) -> AnyResult<String> {
+        let method = pascal_case(name);
+
+        let args = func
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| self.resolve(ty, &format!("{method}Arg{i}")))
+            .collect::<AnyResult<Vec<_>>>()?;
+        let rets = func
+            .rets
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| self.resolve(ty, &format!("{method}Ret{i}")))
+            .collect::<AnyResult<Vec<_>>>()?;
+
+        let params = args
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| format!("{} arg{i}", ty.name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let values = args
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| ty.encode(&format!("arg{i}"), 0))
+            .collect::<Vec<_>>();
+        let values = if values.is_empty() {
+            "new IDLValue[0]".to_string()
+        } else {
+            format!("new IDLValue[] {{ {} }}", values.join(", "))
+        };
+
+        let query = func.modes.contains(&FuncMode::Query);
+        let call = format!("CallCandid(\"{name}\", args, {query})");
+
+        let decoded = rets
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| ty.decode(&format!("rets[{i}]"), 0))
+            .collect::<Vec<_>>();
+        let (ret, stmts) = match rets.len() {
+            0 => ("void".to_string(), format!("{call};")),
+            1 => (
+                rets[0].name(),
+                format!("var rets = {call};\n\n        return {};", decoded[0]),
+            ),
+            _ => (
+                format!(
+                    "({})",
+                    rets.iter().map(CsType::name).collect::<Vec<_>>().join(", ")
+                ),
+                format!(
+                    "var rets = {call};\n\n        return ({});",
+                    decoded.join(", ")
+                ),
+            ),
+        };
+
+        Ok(format!(
+            "
+    public {ret} {method}({params})
+    {{
+        var args = IDLArgs.With({values});
+        {stmts}
+    }}
+"
+        ))
+    }
+
+    fn record(&mut self, name: &str, fields: &[Field]) -> AnyResult<String> {
+        let mut decls = String::new();
+        let mut encodes = String::new();
+        let mut decodes = String::new();
+
+        // Only the named labels survive as strings, the others are hashed by their ids.
+        let by_name = fields
+            .iter()
+            .all(|field| matches!(field.id, Label::Named(_)));
+
+        for Field { id, ty } in fields {
+            let member = member_name(id);
+            let ty = self.resolve(ty, &format!("{name}{}", pascal_case(&member)))?;
+
+            let init = if ty.is_value_type() || matches!(ty, CsType::Opt(_)) {
+                ""
+            } else {
+                " = default!"
+            };
+            writeln!(decls, "    public {} {member}{init};", ty.name())?;
+            let key = match by_name {
+                true => format!("\"{id}\""),
+                false => id.get_id().to_string(),
+            };
+            writeln!(
+                encodes,
+                "            {{ {key}, {} }},",
+                ty.encode(&member, 0)
+            )?;
+            writeln!(
+                decodes,
+                "            {member} = {},",
+                ty.decode(&format!("fields[\"{id}\"]"), 0)
+            )?;
+        }
+        let key_ty = if by_name { "string" } else { "UInt32" };
+
+        Ok(format!(
+            "
+public class {name}
+{{
+{decls}
+    public IDLValue ToIDL()
+    {{
+        return IDLValue.WithRecord(new Dictionary<{key_ty}, IDLValue>
+        {{
+{encodes}        }});
+    }}
+
+    public static {name} FromIDL(IDLValue value)
+    {{
+        var fields = value.AsRecord();
+
+        return new {name}
+        {{
+{decodes}        }};
+    }}
+}}
+"
+        ))
+    }
+
+    fn variant(&mut self, name: &str, fields: &[Field]) -> AnyResult<String> {
+        let mut tags = String::new();
+        let mut ctors = String::new();
+        let mut encodes = String::new();
+        let mut decodes = String::new();
+
+        for (index, Field { id, ty }) in fields.iter().enumerate() {
+            let case = pascal_case(&member_name(id));
+
+            writeln!(tags, "    {case},")?;
+
+            if matches!(self.env.trace_type(ty).map_err(AnyErr::from)?, Type::Null) {
+                write!(
+                    ctors,
+                    "
+    public static {name} {case}()
+    {{
+        return new {name} {{ Tag = {name}Tag.{case}, Value = null }};
+    }}
+"
+                )?;
+                write!(
+                    encodes,
+                    "
+            case {name}Tag.{case}:
+                return IDLValue.WithVariant(\"{id}\", IDLValue.WithNull(), {index});"
+                )?;
+                write!(
+                    decodes,
+                    "
+            case \"{id}\":
+                return {case}();"
+                )?;
+
+                continue;
+            }
+
+            let ty = self.resolve(ty, &format!("{name}{case}"))?;
+            let ty_name = ty.name();
+            write!(
+                ctors,
+                "
+    public static {name} {case}({ty_name} value)
+    {{
+        return new {name} {{ Tag = {name}Tag.{case}, Value = value }};
+    }}
+
+    public {ty_name} As{case}()
+    {{
+        return ({ty_name})Value!;
+    }}
+"
+            )?;
+            write!(
+                encodes,
+                "
+            case {name}Tag.{case}:
+                return IDLValue.WithVariant(\"{id}\", {}, {index});",
+                ty.encode(&format!("As{case}()"), 0)
+            )?;
+            write!(
+                decodes,
+                "
+            case \"{id}\":
+                return {case}({});",
+                ty.decode("inner", 0)
+            )?;
+        }
+
+        Ok(format!(
+            "
+public enum {name}Tag
+{{
+{tags}}}
+
+public class {name}
+{{
+    public {name}Tag Tag {{ get; private set; }}
+    public object? Value {{ get; private set; }}
+{ctors}
+    public IDLValue ToIDL()
+    {{
+        switch (Tag)
+        {{{encodes}
+            default:
+                throw new InvalidOperationException($\"Unknown tag {{Tag}} of {name}\");
+        }}
+    }}
+
+    public static {name} FromIDL(IDLValue value)
+    {{
+        var (tag, inner) = value.AsVariant();
+
+        switch (tag)
+        {{{decodes}
+            default:
+                throw new ArgumentException($\"Unknown tag {{tag}} of {name}\");
+        }}
+    }}
+}}
+"
+        ))
+    }
+
+    /// Map the candid type to C#, `ctx` names the class if it's an anonymous record or variant.
+    fn resolve(&mut self, ty: &Type, ctx: &str) -> AnyResult<CsType> {
+        let cs_type = match ty {
+            Type::Var(name) => {
+                let ty = self.env.find_type(name).map_err(AnyErr::from)?.clone();
+
+                self.resolve(&ty, &pascal_case(name))?
+            }
+            Type::Bool => CsType::Prim("bool", true, "WithBool", "AsBool"),
+            Type::Nat => CsType::Prim("BigInteger", true, "WithNat", "AsNat"),
+            Type::Int => CsType::Prim("BigInteger", true, "WithInt", "AsInt"),
+            Type::Nat8 => CsType::Prim("byte", true, "WithNat8", "AsNat8"),
+            Type::Nat16 => CsType::Prim("UInt16", true, "WithNat16", "AsNat16"),
+            Type::Nat32 => CsType::Prim("UInt32", true, "WithNat32", "AsNat32"),
+            Type::Nat64 => CsType::Prim("UInt64", true, "WithNat64", "AsNat64"),
+            Type::Int8 => CsType::Prim("sbyte", true, "WithInt8", "AsInt8"),
+            Type::Int16 => CsType::Prim("Int16", true, "WithInt16", "AsInt16"),
+            Type::Int32 => CsType::Prim("Int32", true, "WithInt32", "AsInt32"),
+            Type::Int64 => CsType::Prim("Int64", true, "WithInt64", "AsInt64"),
+            Type::Float32 => CsType::Prim("float", true, "WithFloat", "AsFloat"),
+            Type::Float64 => CsType::Prim("double", true, "WithDouble", "AsDouble"),
+            Type::Text => CsType::Prim("string", false, "WithText", "AsText"),
+            Type::Principal => CsType::Prim("Principal", false, "WithPrincipal", "AsPrincipal"),
+            Type::Service(_) => CsType::Prim("Principal", false, "WithService", "AsService"),
+            Type::Func(_) => CsType::Func,
+            Type::Opt(inner) => match self.resolve(inner, ctx)? {
+                // `null` can't tell the nested options apart
+                CsType::Opt(_) | CsType::Raw => CsType::Raw,
+                inner => CsType::Opt(Box::new(inner)),
+            },
+            Type::Vec(inner) => CsType::Vec(Box::new(self.resolve(inner, &format!("{ctx}Item"))?)),
+            Type::Record(_) | Type::Variant(_) => CsType::Class(self.register(ctx, ty)),
+            _ => CsType::Raw,
+        };
+
+        Ok(cs_type)
+    }
+
+    /// Give the name of class for the record or variant, which is unique among the classes.
+    fn register(&mut self, name: &str, ty: &Type) -> String {
+        let mut candidate = name.to_string();
+        let mut n = 1;
+
+        loop {
+            match self.classes.iter().find(|(name, _)| *name == candidate) {
+                Some((_, registered)) if registered == ty => return candidate,
+                None if candidate != self.actor_name => {
+                    self.classes.push((candidate.clone(), ty.clone()));
+
+                    return candidate;
+                }
+                _ => {
+                    n += 1;
+                    candidate = format!("{name}{n}");
+                }
+            }
+        }
+    }
+}
+
+/// `get_delegation` to `GetDelegation`.
+fn pascal_case(name: &str) -> String {
+    let name: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// The C# member name of a field, which keeps the name in candid as possible.
+fn member_name(label: &Label) -> String {
+    match label {
+        Label::Named(name) => {
+            let name: String = name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+
+            if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+                format!("_{name}")
+            } else if KEYWORDS.contains(&name.as_str()) {
+                format!("@{name}")
+            } else {
+                name
+            }
+        }
+        Label::Unnamed(n) => format!("Item{n}"),
+        Label::Id(n) => format!("_{n}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candid::idl_value::idl_value_ct_record_with_ids;
+    use crate::tests_util::{apply_ptr, panic_err_cb};
+    use candid::parser::value::{IDLField, IDLValue};
+    use candid::{check_prog, IDLArgs, IDLProg};
+    use libc::c_int;
+
+    const DID_CONTENT: &str = r#"
+type Key = blob;
+type Purpose = variant { recovery; authentication };
+type Device = record { pubkey : Key; alias : text; credential : opt record { id : nat64 } };
+type Result = variant { ok : vec Device; err : text };
+service : {
+  lookup : (nat64) -> (Result) query;
+  add : (nat64, Device, Purpose) -> ();
+  stats : () -> (nat, record { text; text });
+}
+"#;
+
+    fn generate(did_content: &str) -> String {
+        let ast = did_content.parse::<IDLProg>().unwrap();
+        let mut env = TypeEnv::new();
+        let actor = check_prog(&mut env, &ast).unwrap().unwrap();
+
+        CSharpGenerator::new(&env, "Directory")
+            .generate(&actor)
+            .unwrap()
+    }
+
+    #[test]
+    fn generate_should_work() {
+        let src = generate(DID_CONTENT);
+
+        for expected in [
+            "public class Directory",
+            "public Result Lookup(UInt64 arg0)",
+            "var rets = CallCandid(\"lookup\", args, true);",
+            "public void Add(UInt64 arg0, Device arg1, Purpose arg2)",
+            "public (BigInteger, StatsRet1) Stats()",
+            "public byte[] pubkey = default!;",
+            "public DeviceCredential? credential;",
+            "{ \"credential\", (credential == null ? IDLValue.WithNone() : IDLValue.WithOpt(credential!.ToIDL())) },",
+            "public UInt64 id;",
+            "public string Item0 = default!;",
+            "return IDLValue.WithRecord(new Dictionary<UInt32, IDLValue>",
+            "{ 0, IDLValue.WithText(Item0) },",
+            "Item0 = fields[\"0\"].AsText(),",
+            "public static Purpose Recovery()",
+            "return IDLValue.WithVariant(\"recovery\", IDLValue.WithNull(), 1);",
+            "CallCandid(\"add\", args, false);",
+            "var args = IDLArgs.With(new IDLValue[0]);",
+            "public Device[] AsOk()",
+            "return Ok(inner.AsVec().Select(e0 => Device.FromIDL(e0)).ToArray());",
+        ] {
+            assert!(src.contains(expected), "{expected} not found in\n{src}");
+        }
+    }
+
+    #[test]
+    fn tuple_record_should_encode() {
+        let src = generate(DID_CONTENT);
+        assert!(src.contains("{ 1, IDLValue.WithText(Item1) },"));
+
+        let ast = DID_CONTENT.parse::<IDLProg>().unwrap();
+        let mut env = TypeEnv::new();
+        let actor = check_prog(&mut env, &ast).unwrap().unwrap();
+        let tuple = env.get_method(&actor, "stats").unwrap().rets[1].clone();
+
+        // What the generated `ToIDL` builds, then sends as text like `Agent.Query` does.
+        let ids = [0u32, 1];
+        let vals = [
+            &IDLValue::Text("a".into()) as *const IDLValue,
+            &IDLValue::Text("b".into()),
+        ];
+        let mut ptr = apply_ptr::<IDLValue>();
+        idl_value_ct_record_with_ids(
+            ids.as_ptr(),
+            ids.len() as c_int,
+            vals.as_ptr(),
+            vals.len() as c_int,
+            &mut ptr,
+            panic_err_cb,
+        );
+        let value = unsafe { Box::from_raw(ptr as *mut IDLValue) };
+
+        let args = IDLArgs::new(&[*value])
+            .to_string()
+            .parse::<IDLArgs>()
+            .unwrap();
+        let bytes = args
+            .to_bytes_with_types(&env, std::slice::from_ref(&tuple))
+            .unwrap();
+        let decoded =
+            IDLArgs::from_bytes_with_types(&bytes, &env, std::slice::from_ref(&tuple)).unwrap();
+        assert_eq!(decoded.to_string(), r#"(record { "a"; "b" })"#);
+
+        // The labels named by the digits are hashed, which don't match the tuple.
+        let named = IDLArgs::new(&[IDLValue::Record(vec![
+            IDLField {
+                id: Label::Named("0".into()),
+                val: IDLValue::Text("a".into()),
+            },
+            IDLField {
+                id: Label::Named("1".into()),
+                val: IDLValue::Text("b".into()),
+            },
+        ])]);
+        assert!(named.to_bytes_with_types(&env, &[tuple]).is_err());
+    }
+
+    #[test]
+    fn register_should_work() {
+        let env = TypeEnv::new();
+        let mut generator = CSharpGenerator::new(&env, "Directory");
+
+        let record = Type::Record(Vec::new());
+        let variant = Type::Variant(Vec::new());
+
+        assert_eq!(generator.register("Device", &record), "Device");
+        assert_eq!(generator.register("Device", &record), "Device");
+        assert_eq!(generator.register("Device", &variant), "Device2");
+        assert_eq!(generator.register("Directory", &record), "Directory2");
+    }
+
+    #[test]
+    fn names_should_work() {
+        assert_eq!(pascal_case("get_delegation"), "GetDelegation");
+        assert_eq!(pascal_case("http_request"), "HttpRequest");
+        assert_eq!(pascal_case("2fa"), "_2fa");
+
+        assert_eq!(member_name(&Label::Named("class".to_string())), "@class");
+        assert_eq!(member_name(&Label::Named("max-age".to_string())), "max_age");
+        assert_eq!(member_name(&Label::Unnamed(1)), "Item1");
+    }
+}
@@ -0,0 +1,98 @@
+//! # Intro
+//!
+//! Generate the typed bindings of a canister from its candid file, which call it through the
+//! existing agent.
+
+mod csharp;
+
+pub use csharp::CSharpGenerator;
+
+use crate::{AnyErr, AnyResult, StateCode, UnsizedCallBack};
+use anyhow::bail;
+use candid::{check_prog, IDLProg, TypeEnv};
+use libc::c_char;
+use std::ffi::{CStr, CString};
+
+/// Generate the C# source of the service defined by `did_content`: a class for every record and
+/// variant, and an actor class named `class_name` with a method for every service method.
+pub fn generate_csharp(did_content: &str, class_name: &str) -> AnyResult<String> {
+    let ast = did_content.parse::<IDLProg>().map_err(AnyErr::from)?;
+
+    let mut env = TypeEnv::new();
+    let actor = match check_prog(&mut env, &ast).map_err(AnyErr::from)? {
+        Some(actor) => actor,
+        None => bail!("The candid file doesn't define a service"),
+    };
+
+    CSharpGenerator::new(&env, class_name).generate(&actor)
+}
+
+/// Give the generated C# source of the service defined by `did_content` to `ret_cb`, of which the
+/// actor class is named `class_name`.
+#[no_mangle]
+pub extern "C" fn codegen_csharp(
+    did_content: *const c_char,
+    class_name: *const c_char,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<Vec<u8>> {
+        let did_content = unsafe { CStr::from_ptr(did_content).to_str() }?;
+        let class_name = unsafe { CStr::from_ptr(class_name).to_str() }?;
+
+        let src = CString::new(generate_csharp(did_content, class_name)?)?;
+
+        Ok(src.into_bytes_with_nul())
+    };
+
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, once())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests_util::empty_err_cb;
+    use libc::c_int;
+    use std::sync::Mutex;
+
+    const II_DID_CONTENT: &str = include_str!("../agent/rdmx6-jaaaa-aaaaa-aaadq-cai.did");
+
+    #[test]
+    #[allow(clippy::manual_c_str_literals)]
+    fn codegen_csharp_should_work() {
+        static SRC: Mutex<String> = Mutex::new(String::new());
+
+        extern "C" fn ret_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const c_char) };
+
+            *SRC.lock().unwrap() = c_str.to_str().unwrap().to_string();
+        }
+
+        let did_content = II_DID_CONTENT.to_string() + "\0";
+
+        assert_eq!(
+            codegen_csharp(
+                did_content.as_ptr() as *const c_char,
+                b"InternetIdentity\0".as_ptr() as *const c_char,
+                ret_cb,
+                empty_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let src = SRC.lock().unwrap();
+        assert!(src.contains("public class InternetIdentity"));
+        assert!(src.contains("public DeviceData[] Lookup(UInt64 arg0)"));
+        assert!(src.contains("public class DeviceData"));
+
+        assert_eq!(
+            codegen_csharp(
+                b"type A = nat;\0".as_ptr() as *const c_char,
+                b"A\0".as_ptr() as *const c_char,
+                ret_cb,
+                empty_err_cb
+            ),
+            StateCode::Err
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use crate::signature::KeyAlgorithm;
+use crate::AnyResult;
+use anyhow::bail;
+use ic_agent::{Identity, Signature};
+use ic_types::Principal;
+use libc::{c_int, c_void};
+
+/// A callback which signs the message outside of this library.
+///
+/// It's called with the context, the message, and a buffer of [`MAX_SIGNATURE_LEN`] bytes to
+/// write the signature into. It returns the length of signature, or a negative number on failure.
+///
+/// The signature is in the form which IC expects, e.g. `r || s` of 64 bytes for ECDSA rather than
+/// the DER encoded one.
+pub type ExternalSignCallBack =
+    extern "C" fn(*const c_void, *const u8, c_int, *mut u8, c_int) -> c_int;
+
+pub const MAX_SIGNATURE_LEN: usize = 256;
+
+/// An [`Identity`] of which the key is kept outside, e.g. in the Secure Enclave or the Android
+/// Keystore, and which signs through [`ExternalSignCallBack`].
+pub struct ExternalIdentity {
+    public_key: Vec<u8>,
+    sign_cb: ExternalSignCallBack,
+    // The pointer of context is opaque to this library, kept as an integer to be `Send + Sync`.
+    context: usize,
+}
+
+impl ExternalIdentity {
+    /// `public_key` is DER encoded, of Ed25519, Secp256k1 or P-256.
+    pub fn new(
+        public_key: Vec<u8>,
+        sign_cb: ExternalSignCallBack,
+        context: *const c_void,
+    ) -> AnyResult<Self> {
+        match KeyAlgorithm::detect(&public_key)? {
+            (KeyAlgorithm::CanisterSig, _) => bail!("The canister signatures can't be external"),
+            _ => Ok(Self {
+                public_key,
+                sign_cb,
+                context: context as usize,
+            }),
+        }
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+}
+
+impl Identity for ExternalIdentity {
+    fn sender(&self) -> Result<Principal, String> {
+        Ok(Principal::self_authenticating(&self.public_key))
+    }
+
+    fn sign(&self, blob: &[u8]) -> Result<Signature, String> {
+        let mut signature = [0u8; MAX_SIGNATURE_LEN];
+
+        let len = (self.sign_cb)(
+            self.context as *const c_void,
+            blob.as_ptr(),
+            blob.len() as c_int,
+            signature.as_mut_ptr(),
+            signature.len() as c_int,
+        );
+
+        if len < 0 || len as usize > MAX_SIGNATURE_LEN {
+            return Err(format!("The external signer failed with {}", len));
+        }
+
+        Ok(Signature {
+            public_key: Some(self.public_key.clone()),
+            signature: Some(signature[..len as usize].to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests_util::{basic_sign_cb, failed_sign_cb, BASIC_IDENTITY_FILE};
+    use ic_agent::identity::BasicIdentity;
+
+    #[test]
+    fn external_identity_should_work() {
+        let basic = BasicIdentity::from_pem(BASIC_IDENTITY_FILE.as_bytes()).unwrap();
+        let expected = basic.sign(b"message").unwrap();
+
+        let external = ExternalIdentity::new(
+            expected.public_key.clone().unwrap(),
+            basic_sign_cb,
+            &basic as *const BasicIdentity as *const c_void,
+        )
+        .unwrap();
+
+        assert_eq!(external.sender(), basic.sender());
+
+        let signature = external.sign(b"message").unwrap();
+        assert_eq!(signature.public_key, expected.public_key);
+        assert_eq!(signature.signature, expected.signature);
+    }
+
+    #[test]
+    fn external_identity_should_fail() {
+        let basic = BasicIdentity::from_pem(BASIC_IDENTITY_FILE.as_bytes()).unwrap();
+        let public_key = basic.sign(&[]).unwrap().public_key.unwrap();
+
+        assert!(ExternalIdentity::new(vec![1, 2, 3], failed_sign_cb, std::ptr::null()).is_err());
+
+        let external = ExternalIdentity::new(public_key, failed_sign_cb, std::ptr::null()).unwrap();
+        assert!(external.sign(b"message").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{AnyErr, AnyResult};
+use anyhow::{anyhow, bail};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::ff::PrimeField;
+use k256::elliptic_curve::rand_core::{OsRng, RngCore};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{NonZeroScalar, Scalar, SecretKey};
+use sha2::Sha512;
+
+/// The coin type of ICP registered in SLIP-44.
+pub const ICP_COIN_TYPE: u32 = 223;
+
+const HARDENED: u32 = 0x8000_0000;
+
+/// Generate an English BIP-39 mnemonic of 12, 15, 18, 21 or 24 words.
+pub fn generate_mnemonic(word_count: usize) -> AnyResult<String> {
+    if ![12, 15, 18, 21, 24].contains(&word_count) {
+        bail!("The word count {} of mnemonic is not supported", word_count);
+    }
+
+    let mut entropy = vec![0u8; word_count / 3 * 4];
+    OsRng.fill_bytes(&mut entropy);
+
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(AnyErr::msg)?;
+
+    Ok(mnemonic.to_string())
+}
+
+/// Derive the Secp256k1 key of the mnemonic along the ICP path `m/44'/223'/0'/0/index`.
+pub fn secp256k1_from_mnemonic(phrase: &str, passphrase: &str, index: u32) -> AnyResult<SecretKey> {
+    if index >= HARDENED {
+        bail!("The index {} of address is out of range", index);
+    }
+
+    let mnemonic = Mnemonic::parse(phrase).map_err(AnyErr::msg)?;
+    let seed = mnemonic.to_seed(passphrase);
+
+    let path = [44 | HARDENED, ICP_COIN_TYPE | HARDENED, HARDENED, 0, index];
+
+    let key = path
+        .iter()
+        .try_fold(ExtendedKey::master(&seed)?, |key, &index| key.child(index))?;
+
+    Ok(key.secret_key)
+}
+
+/// The extended private key of BIP-32.
+struct ExtendedKey {
+    secret_key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    fn master(seed: &[u8]) -> AnyResult<Self> {
+        Self::from_hmac(b"Bitcoin seed", &[seed])
+    }
+
+    fn child(self, index: u32) -> AnyResult<Self> {
+        let index_bytes = index.to_be_bytes();
+
+        let child = if index >= HARDENED {
+            let secret = self.secret_key.to_be_bytes();
+
+            Self::from_hmac(&self.chain_code, &[&[0u8], &secret, &index_bytes])?
+        } else {
+            let public_key = self.secret_key.public_key().to_encoded_point(true);
+
+            Self::from_hmac(&self.chain_code, &[public_key.as_bytes(), &index_bytes])?
+        };
+
+        // The child key is the tweak added to the parent key.
+        let tweaked = *child.secret_key.to_nonzero_scalar().as_ref()
+            + self.secret_key.to_nonzero_scalar().as_ref();
+        let secret_key = Option::<NonZeroScalar>::from(NonZeroScalar::new(tweaked))
+            .map(SecretKey::from)
+            .ok_or_else(|| anyhow!("The child key {} is invalid", index))?;
+
+        Ok(Self {
+            secret_key,
+            chain_code: child.chain_code,
+        })
+    }
+
+    /// Split `HMAC-SHA512(key, data)` into the key and the chain code.
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> AnyResult<Self> {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+        data.iter().for_each(|data| mac.update(data));
+        let output = mac.finalize().into_bytes();
+
+        let (left, right) = output.split_at(32);
+
+        let scalar = Option::<Scalar>::from(Scalar::from_repr(*k256::FieldBytes::from_slice(left)))
+            .ok_or_else(|| anyhow!("The derived key is out of range"))?;
+        let secret_key = Option::<NonZeroScalar>::from(NonZeroScalar::new(scalar))
+            .map(SecretKey::from)
+            .ok_or_else(|| anyhow!("The derived key is zero"))?;
+
+        Ok(Self {
+            secret_key,
+            chain_code: right.try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extended_key_should_work() {
+        // The test vector 1 of BIP-32
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+
+        let master = ExtendedKey::master(&seed).unwrap();
+        assert_eq!(
+            hex::encode(master.secret_key.to_be_bytes()),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code),
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508"
+        );
+
+        let key = [HARDENED, 1, 2 | HARDENED]
+            .iter()
+            .try_fold(master, |key, &index| key.child(index))
+            .unwrap();
+        assert_eq!(
+            hex::encode(key.secret_key.to_be_bytes()),
+            "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca"
+        );
+    }
+
+    #[test]
+    fn generate_mnemonic_should_work() {
+        let phrase = generate_mnemonic(24).unwrap();
+        assert_eq!(phrase.split_whitespace().count(), 24);
+
+        let first = secp256k1_from_mnemonic(&phrase, "", 0).unwrap();
+        let second = secp256k1_from_mnemonic(&phrase, "", 1).unwrap();
+        let with_passphrase = secp256k1_from_mnemonic(&phrase, "passphrase", 0).unwrap();
+
+        assert_eq!(first, secp256k1_from_mnemonic(&phrase, "", 0).unwrap());
+        assert_ne!(first, second);
+        assert_ne!(first, with_passphrase);
+    }
+
+    #[test]
+    fn secp256k1_from_mnemonic_should_fail() {
+        assert!(generate_mnemonic(13).is_err());
+        assert!(secp256k1_from_mnemonic("not a mnemonic", "", 0).is_err());
+
+        let phrase = generate_mnemonic(12).unwrap();
+        assert!(secp256k1_from_mnemonic(&phrase, "", HARDENED).is_err());
+    }
+}
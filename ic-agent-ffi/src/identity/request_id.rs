@@ -0,0 +1,180 @@
+use crate::AnyResult;
+use anyhow::bail;
+use serde_cbor::Value;
+use sha2::{Digest, Sha256};
+
+/// The domain separator prepended to the request id before signing.
+pub const IC_REQUEST_DOMAIN_SEPARATOR: &[u8] = b"\x0Aic-request";
+
+/// The request id of the CBOR encoded content map of a call, query or read_state request.
+pub fn request_id_of(content: &[u8]) -> AnyResult<[u8; 32]> {
+    let content: Value = serde_cbor::from_slice(content)?;
+
+    if !matches!(untag(&content), Value::Map(_)) {
+        bail!("The content of request is not a map");
+    }
+
+    hash_of(&content)
+}
+
+/// The bytes to be signed for the request with `request_id`.
+pub fn signable_of(request_id: &[u8; 32]) -> Vec<u8> {
+    [IC_REQUEST_DOMAIN_SEPARATOR, request_id].concat()
+}
+
+/// The representation-independent hash of the value.
+fn hash_of(value: &Value) -> AnyResult<[u8; 32]> {
+    let hash = match untag(value) {
+        Value::Integer(n) => {
+            let mut leb128 = Vec::new();
+            write_leb128(&mut leb128, *n)?;
+
+            Sha256::digest(leb128)
+        }
+        Value::Text(text) => Sha256::digest(text.as_bytes()),
+        Value::Bytes(bytes) => Sha256::digest(bytes),
+        Value::Array(values) => {
+            let mut hasher = Sha256::new();
+            for value in values {
+                hasher.update(hash_of(value)?);
+            }
+
+            hasher.finalize()
+        }
+        Value::Map(map) => {
+            let mut fields = map
+                .iter()
+                .map(|(key, value)| {
+                    let key = match untag(key) {
+                        Value::Text(text) => Sha256::digest(text.as_bytes()),
+                        Value::Bytes(bytes) => Sha256::digest(bytes),
+                        _ => bail!("The key of map is neither text nor bytes"),
+                    };
+
+                    Ok([key.as_slice(), &hash_of(value)?].concat())
+                })
+                .collect::<AnyResult<Vec<_>>>()?;
+            fields.sort();
+
+            Sha256::digest(fields.concat())
+        }
+        value => bail!("The value {:?} can't be hashed", value),
+    };
+
+    Ok(hash.into())
+}
+
+fn untag(value: &Value) -> &Value {
+    match value {
+        Value::Tag(_, value) => untag(value),
+        value => value,
+    }
+}
+
+/// Unsigned LEB128 for the naturals, signed LEB128 for the negative integers.
+fn write_leb128(buf: &mut Vec<u8>, mut n: i128) -> AnyResult<()> {
+    if n >= 0 {
+        if n > u64::MAX as i128 {
+            bail!("The integer {} is out of range", n);
+        }
+
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+
+            if n == 0 {
+                buf.push(byte);
+                break;
+            }
+            buf.push(byte | 0x80);
+        }
+    } else {
+        if n < i64::MIN as i128 {
+            bail!("The integer {} is out of range", n);
+        }
+
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+
+            if n == -1 && byte & 0x40 != 0 {
+                buf.push(byte);
+                break;
+            }
+            buf.push(byte | 0x80);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Content {
+        request_type: &'static str,
+        #[serde(with = "serde_bytes")]
+        canister_id: Vec<u8>,
+        method_name: &'static str,
+        #[serde(with = "serde_bytes")]
+        arg: Vec<u8>,
+        ingress_expiry: u64,
+    }
+
+    #[test]
+    fn request_id_of_should_work() {
+        // The example in the interface spec of IC, extended by `ingress_expiry`
+        let content = Content {
+            request_type: "call",
+            canister_id: vec![0, 0, 0, 0, 0, 0, 0x04, 0xd2],
+            method_name: "hello",
+            arg: b"DIDL\x00\xfd\x2a".to_vec(),
+            ingress_expiry: 1_685_570_400_000_000_000,
+        };
+
+        let request_id = request_id_of(&serde_cbor::to_vec(&content).unwrap()).unwrap();
+        let expected = ic_agent::request_id::to_request_id(&content).unwrap();
+
+        assert_eq!(request_id.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn request_id_of_spec_example_should_work() {
+        let content = serde_cbor::to_vec(&serde_cbor::Value::Map(
+            [
+                ("request_type", Value::Text("call".into())),
+                (
+                    "canister_id",
+                    Value::Bytes(vec![0, 0, 0, 0, 0, 0, 0x04, 0xd2]),
+                ),
+                ("method_name", Value::Text("hello".into())),
+                ("arg", Value::Bytes(b"DIDL\x00\xfd\x2a".to_vec())),
+            ]
+            .into_iter()
+            .map(|(key, value)| (Value::Text(key.into()), value))
+            .collect(),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            hex::encode(request_id_of(&content).unwrap()),
+            "8781291c347db32a9d8c10eb62b710fce5a93be676474c42babc74c51858f94b"
+        );
+    }
+
+    #[test]
+    fn write_leb128_should_work() {
+        let leb128 = |n| {
+            let mut buf = Vec::new();
+            write_leb128(&mut buf, n).unwrap();
+            buf
+        };
+
+        assert_eq!(leb128(0), vec![0x00]);
+        assert_eq!(leb128(624485), vec![0xe5, 0x8e, 0x26]);
+        assert_eq!(leb128(-123456), vec![0xc0, 0xbb, 0x78]);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{AnyErr, AnyResult};
+use anyhow::bail;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::SecretKey;
+use pem::Pem;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+/// The DER prefix of an Ed25519 public key, followed by the 32 bytes key.
+pub(crate) const ED25519_DER_PREFIX: &[u8] = b"\x30\x2a\x30\x05\x06\x03\x2b\x65\x70\x03\x21\x00";
+
+/// The DER prefix of a Secp256k1 public key, followed by the 65 bytes uncompressed point.
+pub(crate) const SECP256K1_DER_PREFIX: &[u8] =
+    b"\x30\x56\x30\x10\x06\x07\x2a\x86\x48\xce\x3d\x02\x01\x06\x05\x2b\x81\x04\x00\x0a\x03\x42\x00";
+
+/// The PKCS#8 v2 document of Ed25519 is this prefix, the seed, the infix then the public key.
+const PKCS8_V2_PREFIX: &[u8] = b"\x30\x53\x02\x01\x01\x30\x05\x06\x03\x2b\x65\x70\x04\x22\x04\x20";
+const PKCS8_V2_INFIX: &[u8] = b"\xa1\x23\x03\x21\x00";
+
+const SECP256K1_PARAMETERS: &[u8] = b"\x06\x05\x2b\x81\x04\x00\x0a";
+
+/// The secret key kept by an identity, so that it can be exported later.
+#[derive(Clone)]
+pub enum Secret {
+    /// The PKCS#8 v2 document, as ring generates and `BasicIdentity::from_pem` reads.
+    Ed25519(Vec<u8>),
+    Secp256k1(SecretKey),
+}
+
+impl Secret {
+    /// The Ed25519 secret from the raw 32 bytes seed.
+    pub fn ed25519_from_bytes(seed: &[u8]) -> AnyResult<Self> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed)?;
+
+        let pkcs8 = [
+            PKCS8_V2_PREFIX,
+            seed,
+            PKCS8_V2_INFIX,
+            key_pair.public_key().as_ref(),
+        ]
+        .concat();
+
+        Ok(Self::Ed25519(pkcs8))
+    }
+
+    /// The Secp256k1 secret from the raw 32 bytes big-endian scalar.
+    pub fn secp256k1_from_bytes(bytes: &[u8]) -> AnyResult<Self> {
+        let secret_key = SecretKey::from_be_bytes(bytes)?;
+
+        Ok(Self::Secp256k1(secret_key))
+    }
+
+    /// The raw 32 bytes, the seed of Ed25519 or the scalar of Secp256k1.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Ed25519(pkcs8) => pkcs8[PKCS8_V2_PREFIX.len()..][..32].to_vec(),
+            Self::Secp256k1(secret_key) => secret_key.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// The PEM in the same format as dfx, which the `identity_*_from_pem` functions can read.
+    pub fn to_pem(&self) -> AnyResult<String> {
+        let pems = match self {
+            Self::Ed25519(pkcs8) => vec![Pem {
+                tag: "PRIVATE KEY".to_string(),
+                contents: pkcs8.clone(),
+            }],
+            Self::Secp256k1(secret_key) => vec![
+                Pem {
+                    tag: "EC PARAMETERS".to_string(),
+                    contents: SECP256K1_PARAMETERS.to_vec(),
+                },
+                Pem {
+                    tag: "EC PRIVATE KEY".to_string(),
+                    contents: secret_key.to_sec1_der()?.to_vec(),
+                },
+            ],
+        };
+
+        Ok(pem::encode_many(&pems))
+    }
+
+    /// The DER encoded public key, same as the one in the signatures.
+    pub fn public_key(&self) -> AnyResult<Vec<u8>> {
+        match self {
+            Self::Ed25519(pkcs8) => {
+                let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8).map_err(AnyErr::from)?;
+
+                Ok([ED25519_DER_PREFIX, key_pair.public_key().as_ref()].concat())
+            }
+            Self::Secp256k1(secret_key) => {
+                let point = secret_key.public_key().to_encoded_point(false);
+
+                Ok([SECP256K1_DER_PREFIX, point.as_bytes()].concat())
+            }
+        }
+    }
+}
+
+/// Read the [`Secret`] from the PEM of `PRIVATE KEY` in PKCS#8 v2.
+pub(crate) fn ed25519_from_pem(pem: &str) -> AnyResult<Secret> {
+    let pem = pem::parse(pem)?;
+
+    Ok(Secret::Ed25519(pem.contents))
+}
+
+/// Same as `Secp256k1Identity::from_pem`, but keeps the [`SecretKey`].
+pub(crate) fn secp256k1_from_pem(pem: &str) -> AnyResult<Secret> {
+    for pem in pem::parse_many(pem)? {
+        if pem.tag == "EC PARAMETERS" && pem.contents != SECP256K1_PARAMETERS {
+            bail!("The curve of the key is not Secp256k1");
+        }
+
+        if pem.tag == "EC PRIVATE KEY" {
+            let secret_key = SecretKey::from_sec1_der(&pem.contents)?;
+
+            return Ok(Secret::Secp256k1(secret_key));
+        }
+    }
+
+    bail!("No EC private key found in the PEM")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: &str = "bf6be1704ab20e29aae6011d8eab81fcee06cbc2cbcf6d82a0df0e9a1f5d104f";
+
+    #[test]
+    fn ed25519_should_work() {
+        let secret = Secret::ed25519_from_bytes(&hex::decode(SEED).unwrap()).unwrap();
+
+        assert_eq!(hex::encode(secret.to_bytes()), SEED);
+        assert_eq!(
+            hex::encode(secret.public_key().unwrap()),
+            "302a300506032b657003210042904d4d2f85f8ddc9574b52c56ae89c7a288a5bd3618d54c4a54e8ba47f5073"
+        );
+
+        let pem = secret.to_pem().unwrap();
+        let secret_infer = ed25519_from_pem(&pem).unwrap();
+        assert_eq!(secret_infer.to_bytes(), secret.to_bytes());
+    }
+
+    #[test]
+    fn secp256k1_should_work() {
+        let secret = Secret::secp256k1_from_bytes(&hex::decode(SEED).unwrap()).unwrap();
+
+        assert_eq!(hex::encode(secret.to_bytes()), SEED);
+        assert_eq!(secret.public_key().unwrap().len(), 88);
+
+        let pem = secret.to_pem().unwrap();
+        let secret_infer = secp256k1_from_pem(&pem).unwrap();
+        assert_eq!(secret_infer.to_bytes(), secret.to_bytes());
+    }
+
+    #[test]
+    fn secret_from_bytes_should_fail() {
+        assert!(Secret::ed25519_from_bytes(&[0u8; 31]).is_err());
+        assert!(Secret::secp256k1_from_bytes(&[0u8; 32]).is_err());
+    }
+}
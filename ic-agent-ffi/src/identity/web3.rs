@@ -0,0 +1,333 @@
+use crate::{AnyErr, AnyResult};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use anyhow::{anyhow, bail};
+use hmac::Hmac;
+use k256::elliptic_curve::rand_core::{OsRng, RngCore};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::SecretKey;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+pub const VERSION: u32 = 3;
+
+/// The `log2(n)` of scrypt used by geth and MetaMask on export.
+pub const STANDARD_SCRYPT_LOG_N: u8 = 18;
+
+const CIPHER: &str = "aes-128-ctr";
+const PRF: &str = "hmac-sha256";
+const DKLEN: usize = 32;
+
+// The upper bounds of the kdf params read from a file, so that a crafted one can't exhaust the
+// memory or the CPU of the host. `2^20 * 8` of scrypt takes 1 GiB.
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 8;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_PBKDF2_C: u32 = 2_000_000;
+
+/// A key file of Web3 Secret Storage, known as the Ethereum keystore v3.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Web3KeyStore {
+    pub version: u32,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(alias = "Crypto")]
+    pub crypto: Web3Crypto,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Web3Crypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    #[serde(flatten)]
+    pub kdf: Kdf,
+    pub mac: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kdf", content = "kdfparams", rename_all = "lowercase")]
+pub enum Kdf {
+    Scrypt(ScryptParams),
+    Pbkdf2(Pbkdf2Params),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub dklen: usize,
+    pub n: u32,
+    pub r: u32,
+    pub p: u32,
+    pub salt: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pbkdf2Params {
+    pub dklen: usize,
+    pub c: u32,
+    pub prf: String,
+    pub salt: String,
+}
+
+impl Web3KeyStore {
+    pub fn from_json(json: &str) -> AnyResult<Self> {
+        let key_store: Self = serde_json::from_str(json)?;
+
+        if key_store.version != VERSION {
+            bail!(
+                "The version {} of Web3 key store is not supported, expected {}",
+                key_store.version,
+                VERSION
+            );
+        }
+
+        Ok(key_store)
+    }
+
+    pub fn to_json(&self) -> AnyResult<String> {
+        serde_json::to_string(self).map_err(AnyErr::from)
+    }
+
+    /// Encrypt the Secp256k1 key with scrypt of `2^log_n` rounds, a random salt and iv.
+    pub fn encrypt(secret_key: &SecretKey, pwd: &str, log_n: u8) -> AnyResult<Self> {
+        if log_n > MAX_SCRYPT_LOG_N {
+            bail!("The log_n {} of scrypt exceeds {}", log_n, MAX_SCRYPT_LOG_N);
+        }
+
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+
+        let kdf = Kdf::Scrypt(ScryptParams {
+            dklen: DKLEN,
+            n: 1 << log_n,
+            r: 8,
+            p: 1,
+            salt: hex::encode(salt),
+        });
+        let derived = kdf.derive(pwd)?;
+
+        let mut ciphertext = secret_key.to_be_bytes().to_vec();
+        Aes128Ctr::new(derived[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+        let mac = Self::mac(&derived, &ciphertext);
+
+        Ok(Self {
+            version: VERSION,
+            id: random_uuid(),
+            address: Some(hex::encode(address_of(secret_key))),
+            crypto: Web3Crypto {
+                cipher: CIPHER.to_string(),
+                cipherparams: CipherParams {
+                    iv: hex::encode(iv),
+                },
+                ciphertext: hex::encode(ciphertext),
+                kdf,
+                mac: hex::encode(mac),
+            },
+        })
+    }
+
+    pub fn decrypt(&self, pwd: &str) -> AnyResult<SecretKey> {
+        let crypto = &self.crypto;
+
+        if crypto.cipher != CIPHER {
+            bail!("The cipher {} is not supported", crypto.cipher);
+        }
+
+        let derived = crypto.kdf.derive(pwd)?;
+        let mut ciphertext = hex::decode(&crypto.ciphertext)?;
+        let mac = hex::decode(&crypto.mac)?;
+
+        ring::constant_time::verify_slices_are_equal(&Self::mac(&derived, &ciphertext), &mac)
+            .map_err(|_| anyhow!("Failed to decrypt the Web3 key store, wrong password?"))?;
+
+        let iv: [u8; 16] = hex::decode(&crypto.cipherparams.iv)?
+            .as_slice()
+            .try_into()?;
+        Aes128Ctr::new(derived[..16].into(), (&iv).into()).apply_keystream(&mut ciphertext);
+
+        SecretKey::from_be_bytes(&ciphertext).map_err(AnyErr::from)
+    }
+
+    fn mac(derived: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+        Keccak256::new()
+            .chain_update(&derived[16..32])
+            .chain_update(ciphertext)
+            .finalize()
+            .into()
+    }
+}
+
+impl Kdf {
+    fn derive(&self, pwd: &str) -> AnyResult<Vec<u8>> {
+        let (dklen, salt) = match self {
+            Kdf::Scrypt(params) => (params.dklen, &params.salt),
+            Kdf::Pbkdf2(params) => (params.dklen, &params.salt),
+        };
+
+        if dklen != DKLEN {
+            bail!("The dklen {} is not {}", dklen, DKLEN);
+        }
+
+        let salt = hex::decode(salt)?;
+        let mut derived = vec![0u8; dklen];
+
+        match self {
+            Kdf::Scrypt(params) => {
+                if !params.n.is_power_of_two() || params.n < 2 {
+                    bail!("The n {} of scrypt is not a power of 2", params.n);
+                }
+
+                let log_n = params.n.trailing_zeros() as u8;
+                if log_n > MAX_SCRYPT_LOG_N || params.r > MAX_SCRYPT_R || params.p > MAX_SCRYPT_P {
+                    bail!(
+                        "The scrypt params exceed the limits: n 2^{}, r {}, p {}",
+                        log_n,
+                        params.r,
+                        params.p
+                    );
+                }
+
+                let params = scrypt::Params::new(log_n, params.r, params.p)
+                    .map_err(|e| anyhow!("Invalid scrypt params: {}", e))?;
+
+                scrypt::scrypt(pwd.as_bytes(), &salt, &params, &mut derived)
+                    .map_err(|e| anyhow!("Failed on scrypt: {}", e))?;
+            }
+            Kdf::Pbkdf2(params) => {
+                if params.prf != PRF {
+                    bail!("The prf {} of pbkdf2 is not supported", params.prf);
+                }
+                if params.c > MAX_PBKDF2_C {
+                    bail!("The c {} of pbkdf2 exceeds {}", params.c, MAX_PBKDF2_C);
+                }
+
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(pwd.as_bytes(), &salt, params.c, &mut derived);
+            }
+        }
+
+        Ok(derived)
+    }
+}
+
+/// The Ethereum address of the key, the last 20 bytes of Keccak-256 over the uncompressed point.
+pub fn address_of(secret_key: &SecretKey) -> [u8; 20] {
+    let point = secret_key.public_key().to_encoded_point(false);
+    let hash = Keccak256::digest(&point.as_bytes()[1..]);
+
+    hash[12..].try_into().expect("Keccak-256 gives 32 bytes")
+}
+
+fn random_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+
+    // Version 4, variant RFC 4122
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "testpassword";
+    const SECRET: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    // The test vector of Web3 Secret Storage Definition.
+    const PBKDF2_JSON: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    #[test]
+    fn decrypt_should_work() {
+        let key_store = Web3KeyStore::from_json(PBKDF2_JSON).unwrap();
+
+        let secret_key = key_store.decrypt(PASSWORD).unwrap();
+        assert_eq!(hex::encode(secret_key.to_be_bytes()), SECRET);
+
+        assert!(key_store.decrypt("wrong password").is_err());
+    }
+
+    #[test]
+    fn encrypt_should_work() {
+        let secret_key = SecretKey::from_be_bytes(&hex::decode(SECRET).unwrap()).unwrap();
+
+        // Light rounds to keep the test fast
+        let key_store = Web3KeyStore::encrypt(&secret_key, PASSWORD, 10).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&key_store.to_json().unwrap()).unwrap();
+
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["crypto"]["kdf"], "scrypt");
+        assert_eq!(json["crypto"]["kdfparams"]["n"], 1024);
+        assert_eq!(json["id"].as_str().unwrap().len(), 36);
+
+        let key_store = Web3KeyStore::from_json(&json.to_string()).unwrap();
+        assert_eq!(key_store.decrypt(PASSWORD).unwrap(), secret_key);
+    }
+
+    #[test]
+    fn decrypt_with_oversized_params_should_fail() {
+        let json: serde_json::Value = serde_json::from_str(PBKDF2_JSON).unwrap();
+
+        for (param, value) in [
+            ("dklen", serde_json::json!(usize::MAX)),
+            ("dklen", serde_json::json!(64)),
+            ("c", serde_json::json!(u32::MAX)),
+        ] {
+            let mut json = json.clone();
+            json["crypto"]["kdfparams"][param] = value;
+
+            let key_store = Web3KeyStore::from_json(&json.to_string()).unwrap();
+            assert!(key_store.decrypt(PASSWORD).is_err());
+        }
+
+        let secret_key = SecretKey::from_be_bytes(&hex::decode(SECRET).unwrap()).unwrap();
+        let key_store = Web3KeyStore::encrypt(&secret_key, PASSWORD, 10).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&key_store.to_json().unwrap()).unwrap();
+
+        for (param, value) in [("n", 1u64 << 31), ("r", 1 << 20), ("p", u32::MAX as u64)] {
+            let mut json = json.clone();
+            json["crypto"]["kdfparams"][param] = value.into();
+
+            let key_store = Web3KeyStore::from_json(&json.to_string()).unwrap();
+            let e = key_store.decrypt(PASSWORD).unwrap_err();
+            assert!(e.to_string().contains("exceed the limits"));
+        }
+
+        assert!(Web3KeyStore::encrypt(&secret_key, PASSWORD, 64).is_err());
+    }
+}
@@ -0,0 +1,680 @@
+//! KeyStore Format Example
+//!
+//! ```json
+//! {
+//!     "version": 3,
+//!     "encoded": ..,
+//!     "salt": ..,
+//!     "nonce": ..,
+//!     "principal": ..,
+//!     "publicKey": ..,
+//!     "meta": {
+//!                 "name": "my account",
+//!                 "whenCreated": "2022-05-29 11:24:15.801607 UTC",
+//!                 "storeSyntax": "pkcs8", // or "sec1"
+//!                 "sigScheme": "ed25519", // or "secp256k1"
+//!                 "encryptScheme": ["argon2", "chacha20-poly1305"],
+//!                 "argon2": {
+//!                             "variant": "argon2i",
+//!                             "version": 19,
+//!                             "memCost": 4096,
+//!                             "timeCost": 3,
+//!                             "lanes": 1
+//!                           }
+//!             }
+//! }
+//! ```
+//!
+//! The version 1 has neither `salt`, `nonce` nor `argon2`, which were fixed for every file.
+//!
+//! The `principal` must be the one of `publicKey`, the versions before 3 have no `publicKey` so
+//! their `principal` is checked only when decrypted.
+
+use crate::identity::{IdentityHandle, Secret};
+use anyhow::bail;
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use chrono::{DateTime, Utc};
+use ic_types::Principal;
+use k256::elliptic_curve::rand_core::OsRng;
+use k256::SecretKey;
+use lazy_static::lazy_static;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::Ed25519KeyPair;
+use serde_derive::{Deserialize, Serialize};
+
+const LEGACY_ARGON2_SALT: &[u8] = b"IDENTITY HOST KEY STORE";
+
+lazy_static! {
+    static ref LEGACY_NONCE: Nonce = *Nonce::from_slice(b"unique nonce");
+}
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// The upper bounds of the argon2 parameters read from a file, so that a tampered one can't make
+/// the host hash for hours or with gigabytes of memory.
+const ARGON2_MAX_MEM_COST: u32 = 1024 * 1024; // in KiB, i.e. 1 GiB
+const ARGON2_MAX_TIME_COST: u32 = 10;
+const ARGON2_MAX_LANES: u32 = 16;
+
+/// The version of format written by [`HostKeyStore`], files without `version` are version 1.
+pub const VERSION: u32 = 3;
+
+/// The first version which keeps the public key.
+const VERSION_WITH_PUBLIC_KEY: u32 = 3;
+
+fn version_before_versioning() -> u32 {
+    1
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostKeyStore {
+    #[serde(default = "version_before_versioning")]
+    version: u32,
+    encoded: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    principal: Principal,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
+    pub meta: HostKeyStoreMeta,
+}
+
+impl HostKeyStore {
+    #[allow(dead_code)]
+    pub fn verify(self, pwd: &str) -> anyhow::Result<Self> {
+        let principal = self.to_identity(pwd)?.sender()?;
+
+        Ok(Self { principal, ..self })
+    }
+
+    pub fn random(name: &str, pwd: &str) -> anyhow::Result<Self> {
+        let rng = SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)?;
+
+        Self::from_pkcs8(name, pwd, pkcs8.as_ref())
+    }
+
+    pub fn random_secp256k1(name: &str, pwd: &str) -> anyhow::Result<Self> {
+        let secret_key = SecretKey::random(OsRng);
+
+        Self::from_secp256k1(name, pwd, &secret_key)
+    }
+
+    /// Store the secret key of an existing identity, e.g. a wallet key imported by the player.
+    pub fn from_identity(name: &str, pwd: &str, identity: &IdentityHandle) -> anyhow::Result<Self> {
+        match identity.secret() {
+            Some(Secret::Ed25519(pkcs8)) => Self::from_pkcs8(name, pwd, pkcs8),
+            Some(Secret::Secp256k1(secret_key)) => Self::from_secp256k1(name, pwd, secret_key),
+            None => bail!("The identity has no secret key to store"),
+        }
+    }
+
+    pub fn from_pkcs8(name: &str, pwd: &str, pkcs8: &[u8]) -> anyhow::Result<Self> {
+        Self::from_secret(name, pwd, SigScheme::Ed25519, pkcs8)
+    }
+
+    pub fn from_secp256k1(name: &str, pwd: &str, secret_key: &SecretKey) -> anyhow::Result<Self> {
+        let sec1 = secret_key.to_sec1_der()?;
+
+        Self::from_secret(name, pwd, SigScheme::Secp256k1, sec1.as_slice())
+    }
+
+    fn from_secret(
+        name: &str,
+        pwd: &str,
+        sig_scheme: SigScheme,
+        secret: &[u8],
+    ) -> anyhow::Result<Self> {
+        let identity = Self::identity_from_secret(sig_scheme, secret)?;
+
+        let mut key_store = Self {
+            version: VERSION,
+            encoded: String::new(),
+            salt: None,
+            nonce: None,
+            principal: identity.sender()?,
+            public_key: Some(base64::encode(identity.public_key()?)),
+            meta: HostKeyStoreMeta::new(name, sig_scheme),
+        };
+        key_store.encrypt_then_encode(secret, pwd)?;
+
+        Ok(key_store)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let key_store: Self = serde_json::from_str(json)?;
+
+        if key_store.version > VERSION {
+            bail!(
+                "The version {} of key store is newer than the supported version {}",
+                key_store.version,
+                VERSION
+            );
+        }
+
+        // Otherwise a tampered file could claim any principal.
+        match &key_store.public_key {
+            Some(public_key) => {
+                let public_key = base64::decode(public_key)?;
+
+                if Principal::self_authenticating(public_key) != key_store.principal {
+                    bail!("The principal of key store doesn't match its public key");
+                }
+            }
+            None if key_store.version >= VERSION_WITH_PUBLIC_KEY => {
+                bail!("The key store misses the public key")
+            }
+            None => {}
+        }
+
+        Ok(key_store)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Decrypt the secret key into an [`IdentityHandle`] of the matching [`IdentityType`].
+    pub fn to_identity(&self, pwd: &str) -> anyhow::Result<IdentityHandle> {
+        let secret = self.decode_then_decrypt(pwd)?;
+
+        self.identity_of(secret.as_slice())
+    }
+
+    /// Change the password, which also upgrades the key store to the latest [`VERSION`].
+    pub fn change_password(&mut self, old_pwd: &str, new_pwd: &str) -> anyhow::Result<()> {
+        let secret = self.decode_then_decrypt(old_pwd)?;
+        let identity = self.identity_of(secret.as_slice())?;
+
+        self.public_key = Some(base64::encode(identity.public_key()?));
+        self.encrypt_then_encode(secret.as_slice(), new_pwd)
+    }
+
+    pub fn principal(&self) -> Principal {
+        self.principal
+    }
+
+    /// The identity of the decrypted `secret`, which must be the one of [`Self::principal`].
+    fn identity_of(&self, secret: &[u8]) -> anyhow::Result<IdentityHandle> {
+        let identity = Self::identity_from_secret(self.meta.sig_scheme, secret)?;
+
+        if identity.sender()? != self.principal {
+            bail!("The key store doesn't keep the key of its principal");
+        }
+
+        Ok(identity)
+    }
+
+    fn identity_from_secret(
+        sig_scheme: SigScheme,
+        secret: &[u8],
+    ) -> anyhow::Result<IdentityHandle> {
+        let secret = match sig_scheme {
+            SigScheme::Ed25519 => Secret::Ed25519(secret.to_vec()),
+            SigScheme::Secp256k1 => Secret::Secp256k1(SecretKey::from_sec1_der(secret)?),
+        };
+
+        IdentityHandle::from_secret(secret)
+    }
+
+    fn hash_password(pwd: &str, salt: &[u8], params: &Argon2Params) -> anyhow::Result<[u8; 32]> {
+        let config = params.to_config()?;
+        let pwd_hash = argon2::hash_raw(pwd.as_bytes(), salt, &config)?;
+
+        let pwd_hash: &[u8; 32] = pwd_hash.as_slice().try_into()?;
+
+        Ok(*pwd_hash)
+    }
+
+    /// Encrypt `content` with a fresh salt and nonce, every encryption must never reuse them.
+    fn encrypt_then_encode(&mut self, content: &[u8], pwd: &str) -> anyhow::Result<()> {
+        let rng = SystemRandom::new();
+
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut salt)?;
+        rng.fill(&mut nonce)?;
+
+        let params = Argon2Params::default();
+        let pwd_hash = Self::hash_password(pwd, &salt, &params)?;
+
+        let key = Key::from_slice(pwd_hash.as_slice());
+        let cipher = ChaCha20Poly1305::new(key);
+        let encrypted = cipher.encrypt(Nonce::from_slice(&nonce), content)?;
+
+        self.version = VERSION;
+        self.encoded = base64::encode(encrypted.as_slice());
+        self.salt = Some(base64::encode(salt));
+        self.nonce = Some(base64::encode(nonce));
+        self.meta.argon2 = Some(params);
+
+        Ok(())
+    }
+
+    fn decode_then_decrypt(&self, pwd: &str) -> anyhow::Result<Vec<u8>> {
+        if self.version == 1 {
+            return Self::legacy_decode_then_decrypt(&self.encoded, pwd);
+        }
+
+        let (salt, nonce, params) = match (&self.salt, &self.nonce, &self.meta.argon2) {
+            (Some(salt), Some(nonce), Some(params)) => (salt, nonce, params),
+            _ => bail!("The key store misses the salt, the nonce or the argon2 parameters"),
+        };
+
+        let encrypted = base64::decode(&self.encoded)?;
+        let salt = base64::decode(salt)?;
+        let nonce = base64::decode(nonce)?;
+
+        if nonce.len() != NONCE_LEN {
+            bail!("The length of nonce isn't {}", NONCE_LEN);
+        }
+
+        let pwd_hash = Self::hash_password(pwd, &salt, params)?;
+        let key = Key::from_slice(pwd_hash.as_slice());
+        let cipher = ChaCha20Poly1305::new(key);
+
+        let pkcs8 = cipher.decrypt(Nonce::from_slice(&nonce), encrypted.as_slice())?;
+
+        Ok(pkcs8)
+    }
+
+    /// Decrypt the version 1, which used the same salt and nonce for every file.
+    fn legacy_decode_then_decrypt(encoded: &str, pwd: &str) -> anyhow::Result<Vec<u8>> {
+        let encrypted = base64::decode(encoded)?;
+
+        let config = argon2::Config::default();
+        let pwd_hash = argon2::hash_raw(pwd.as_bytes(), LEGACY_ARGON2_SALT, &config)?;
+        let key = Key::from_slice(pwd_hash.as_slice());
+        let cipher = ChaCha20Poly1305::new(key);
+
+        let pkcs8 = cipher.decrypt(&LEGACY_NONCE, encrypted.as_slice())?;
+
+        Ok(pkcs8)
+    }
+}
+
+/// The parameters of argon2 used to derive the key from password.
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Argon2Params {
+    variant: String,
+    version: u32,
+    mem_cost: u32,
+    time_cost: u32,
+    lanes: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        let config = argon2::Config::default();
+
+        Self {
+            variant: config.variant.as_lowercase_str().into(),
+            version: config.version.as_u32(),
+            mem_cost: config.mem_cost,
+            time_cost: config.time_cost,
+            lanes: config.lanes,
+        }
+    }
+}
+
+impl Argon2Params {
+    fn to_config(&self) -> anyhow::Result<argon2::Config<'static>> {
+        if self.mem_cost > ARGON2_MAX_MEM_COST
+            || self.time_cost > ARGON2_MAX_TIME_COST
+            || self.lanes > ARGON2_MAX_LANES
+        {
+            bail!(
+                "The argon2 parameters exceed the limits: mem_cost {}, time_cost {}, lanes {}",
+                self.mem_cost,
+                self.time_cost,
+                self.lanes
+            );
+        }
+
+        Ok(argon2::Config {
+            variant: argon2::Variant::from_str(&self.variant)?,
+            version: argon2::Version::from_u32(self.version)?,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.lanes,
+            hash_length: 32,
+            ..argon2::Config::default()
+        })
+    }
+}
+
+/// The signature scheme of the secret key in [`HostKeyStore`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigScheme {
+    Ed25519,
+    Secp256k1,
+}
+
+impl SigScheme {
+    /// The syntax of the secret key encrypted in [`HostKeyStore`].
+    fn store_syntax(&self) -> &'static str {
+        match self {
+            SigScheme::Ed25519 => "pkcs8",
+            SigScheme::Secp256k1 => "sec1",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostKeyStoreMeta {
+    pub name: String,
+    when_created: DateTime<Utc>,
+    store_syntax: String,
+    sig_scheme: SigScheme,
+    encrypt_scheme: (String, String),
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    argon2: Option<Argon2Params>,
+}
+
+impl HostKeyStoreMeta {
+    pub fn new(name: &str, sig_scheme: SigScheme) -> Self {
+        Self {
+            name: name.into(),
+            when_created: Utc::now(),
+            store_syntax: sig_scheme.store_syntax().into(),
+            sig_scheme,
+            encrypt_scheme: ("argon2".into(), "chacha20-poly1305".into()),
+            argon2: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn when_created(&self) -> DateTime<Utc> {
+        self.when_created
+    }
+
+    #[allow(dead_code)]
+    pub fn store_syntax(&self) -> &str {
+        &self.store_syntax
+    }
+
+    #[allow(dead_code)]
+    pub fn sig_scheme(&self) -> SigScheme {
+        self.sig_scheme
+    }
+
+    #[allow(dead_code)]
+    pub fn encrypt_scheme(&self) -> (&str, &str) {
+        (&self.encrypt_scheme.0, &self.encrypt_scheme.1)
+    }
+
+    #[allow(dead_code)]
+    pub fn argon2(&self) -> Option<&Argon2Params> {
+        self.argon2.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityType;
+    use ic_agent::identity::BasicIdentity;
+    use ic_agent::Identity;
+
+    const NAME: &str = "test account";
+    const CONTENT: &[u8] = b"content for tests";
+    const PASSWORD: &str = "ABCDE123456abcde";
+    const ENCODED: &str = "9SS0OTkmaBkLszBx3jqcB4Vyu1PPMCGini7/v3i4MDf4";
+
+    /// The encryption of version 1, only kept to create legacy files.
+    fn legacy_encrypt_then_encode(content: &[u8], pwd: &str) -> anyhow::Result<String> {
+        let config = argon2::Config::default();
+        let pwd_hash = argon2::hash_raw(pwd.as_bytes(), LEGACY_ARGON2_SALT, &config)?;
+
+        let key = Key::from_slice(pwd_hash.as_slice());
+        let cipher = ChaCha20Poly1305::new(key);
+        let encrypted = cipher.encrypt(&LEGACY_NONCE, content)?;
+
+        Ok(base64::encode(encrypted.as_slice()))
+    }
+
+    #[test]
+    fn legacy_encrypt_then_encode_should_work() -> anyhow::Result<()> {
+        let encoded = legacy_encrypt_then_encode(CONTENT, PASSWORD)?;
+
+        assert_eq!(&encoded, ENCODED);
+
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_decode_then_decrypt_should_work() -> anyhow::Result<()> {
+        let content = HostKeyStore::legacy_decode_then_decrypt(ENCODED, PASSWORD)?;
+
+        assert_eq!(content.as_slice(), CONTENT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_then_encode_should_not_reuse_salt_and_nonce() -> anyhow::Result<()> {
+        let rng = SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)?;
+
+        let key_store_a = HostKeyStore::from_pkcs8(NAME, PASSWORD, pkcs8.as_ref())?;
+        let key_store_b = HostKeyStore::from_pkcs8(NAME, PASSWORD, pkcs8.as_ref())?;
+
+        assert_ne!(key_store_a.salt, key_store_b.salt);
+        assert_ne!(key_store_a.nonce, key_store_b.nonce);
+        assert_ne!(key_store_a.encoded, key_store_b.encoded);
+        assert_eq!(key_store_a.meta.argon2(), Some(&Argon2Params::default()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_pkcs8_should_work() -> anyhow::Result<()> {
+        let rng = SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)?;
+
+        let ed25519 = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())?;
+        let identity = BasicIdentity::from_key_pair(ed25519);
+        let principal = identity.sender().map_err(anyhow::Error::msg)?;
+
+        let key_store = HostKeyStore::from_pkcs8(NAME, PASSWORD, pkcs8.as_ref())?;
+
+        let pkcs8_infer = key_store.decode_then_decrypt(PASSWORD)?;
+
+        assert_eq!(pkcs8_infer.as_slice(), pkcs8.as_ref());
+        assert_eq!(key_store.principal, principal);
+        assert_eq!(key_store.meta.name, NAME);
+
+        Ok(())
+    }
+
+    #[test]
+    fn random_should_work() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random(NAME, PASSWORD)?;
+
+        let pkcs8_infer = key_store.decode_then_decrypt(PASSWORD)?;
+
+        let ed25519 = Ed25519KeyPair::from_pkcs8(pkcs8_infer.as_slice())?;
+        let identity = BasicIdentity::from_key_pair(ed25519);
+        let principal = identity.sender().map_err(anyhow::Error::msg)?;
+
+        assert_eq!(key_store.principal, principal);
+        assert_eq!(key_store.meta.name, NAME);
+
+        Ok(())
+    }
+
+    #[test]
+    fn to_identity_should_work() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random(NAME, PASSWORD)?;
+
+        let identity = key_store.to_identity(PASSWORD)?;
+        let principal = identity.sender().unwrap_or(Principal::anonymous());
+
+        assert_eq!(key_store.principal, principal);
+        assert_eq!(identity.ty(), IdentityType::Basic);
+
+        Ok(())
+    }
+
+    #[test]
+    fn random_secp256k1_should_work() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random_secp256k1(NAME, PASSWORD)?;
+
+        assert_eq!(key_store.meta.sig_scheme(), SigScheme::Secp256k1);
+        assert_eq!(key_store.meta.store_syntax(), "sec1");
+
+        let key_store = HostKeyStore::from_json(&key_store.to_json()?)?;
+        let identity = key_store.to_identity(PASSWORD)?;
+
+        assert_eq!(identity.sender()?, key_store.principal);
+        assert_eq!(identity.ty(), IdentityType::Secp256K1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn json_should_work() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random(NAME, PASSWORD)?;
+
+        let key_store_infer = HostKeyStore::from_json(&key_store.to_json()?)?;
+
+        assert_eq!(key_store_infer, key_store);
+        assert_eq!(key_store_infer.version(), VERSION);
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_json_without_version_should_work() -> anyhow::Result<()> {
+        const NEW_PASSWORD: &str = "123456ABCDEabcde";
+
+        let rng = SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)?;
+        let key_store = HostKeyStore::from_pkcs8(NAME, PASSWORD, pkcs8.as_ref())?;
+
+        // The file written before versioning
+        let mut json: serde_json::Value = serde_json::from_str(&key_store.to_json()?)?;
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("version");
+        fields.remove("publicKey");
+        fields.remove("salt");
+        fields.remove("nonce");
+        fields["meta"].as_object_mut().unwrap().remove("argon2");
+        fields["encoded"] = legacy_encrypt_then_encode(pkcs8.as_ref(), PASSWORD)?.into();
+
+        let mut key_store_infer = HostKeyStore::from_json(&json.to_string())?;
+
+        assert_eq!(key_store_infer.version(), 1);
+        assert_eq!(
+            key_store_infer.to_identity(PASSWORD)?.sender()?,
+            key_store.principal
+        );
+
+        // Upgrade to the latest version
+        key_store_infer.change_password(PASSWORD, NEW_PASSWORD)?;
+
+        assert_eq!(key_store_infer.version(), VERSION);
+        assert!(key_store_infer.salt.is_some());
+        assert_eq!(
+            key_store_infer.to_identity(NEW_PASSWORD)?.sender()?,
+            key_store.principal
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_json_with_newer_version_should_fail() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random(NAME, PASSWORD)?;
+
+        let mut json: serde_json::Value = serde_json::from_str(&key_store.to_json()?)?;
+        json["version"] = (VERSION + 1).into();
+
+        assert!(HostKeyStore::from_json(&json.to_string()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_json_with_tampered_principal_should_fail() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random(NAME, PASSWORD)?;
+        let other = HostKeyStore::random(NAME, PASSWORD)?;
+
+        let mut json: serde_json::Value = serde_json::from_str(&key_store.to_json()?)?;
+        json["principal"] = serde_json::to_value(other.principal)?;
+
+        let e = HostKeyStore::from_json(&json.to_string()).unwrap_err();
+        assert!(e.to_string().contains("doesn't match its public key"));
+
+        // Without the public key, it's found only when decrypted.
+        json.as_object_mut().unwrap().remove("publicKey");
+        assert!(HostKeyStore::from_json(&json.to_string()).is_err());
+
+        json["version"] = (VERSION_WITH_PUBLIC_KEY - 1).into();
+        let key_store = HostKeyStore::from_json(&json.to_string())?;
+        assert_eq!(key_store.principal(), other.principal);
+
+        let e = key_store.to_identity(PASSWORD).err().unwrap();
+        assert!(e
+            .to_string()
+            .contains("doesn't keep the key of its principal"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_json_with_oversized_argon2_should_fail() -> anyhow::Result<()> {
+        let key_store = HostKeyStore::random(NAME, PASSWORD)?;
+        let json: serde_json::Value = serde_json::from_str(&key_store.to_json()?)?;
+
+        for (param, value) in [
+            ("memCost", ARGON2_MAX_MEM_COST + 1),
+            ("timeCost", ARGON2_MAX_TIME_COST + 1),
+            ("lanes", ARGON2_MAX_LANES + 1),
+        ] {
+            let mut json = json.clone();
+            json["meta"]["argon2"][param] = value.into();
+
+            let key_store = HostKeyStore::from_json(&json.to_string())?;
+            let e = key_store.decode_then_decrypt(PASSWORD).unwrap_err();
+            assert!(e.to_string().contains("exceed the limits"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn update_pwd_should_work() -> anyhow::Result<()> {
+        const NEW_PASSWORD: &str = "123456ABCDEabcde";
+
+        let mut key_store = HostKeyStore::random(NAME, PASSWORD)?;
+
+        let pkcs8_infer_old = key_store.decode_then_decrypt(PASSWORD)?;
+
+        key_store.change_password(PASSWORD, NEW_PASSWORD)?;
+
+        let pkcs8_infer_new = key_store.decode_then_decrypt(NEW_PASSWORD)?;
+
+        assert_eq!(pkcs8_infer_old, pkcs8_infer_new);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,407 @@
+mod host_keystore;
+
+pub use host_keystore::HostKeyStore;
+
+use crate::identity::IdentityHandle;
+use crate::{ret_thin_ptr, ret_unsized, AnyErr, AnyResult, StateCode, UnsizedCallBack};
+use libc::c_char;
+use std::ffi::{CStr, CString};
+use std::fmt::Display;
+
+/// Create a [`HostKeyStore`] of a random Ed25519 key, encrypted by `pwd`.
+#[no_mangle]
+pub extern "C" fn keystore_create(
+    name: *const c_char,
+    pwd: *const c_char,
+    p2ptr: *mut *const HostKeyStore,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<HostKeyStore> {
+        let name = unsafe { CStr::from_ptr(name).to_str() }?;
+        let pwd = unsafe { CStr::from_ptr(pwd).to_str() }?;
+
+        HostKeyStore::random(name, pwd)
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+/// Create a [`HostKeyStore`] of a random Secp256k1 key, encrypted by `pwd`.
+#[no_mangle]
+pub extern "C" fn keystore_create_secp256k1(
+    name: *const c_char,
+    pwd: *const c_char,
+    p2ptr: *mut *const HostKeyStore,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<HostKeyStore> {
+        let name = unsafe { CStr::from_ptr(name).to_str() }?;
+        let pwd = unsafe { CStr::from_ptr(pwd).to_str() }?;
+
+        HostKeyStore::random_secp256k1(name, pwd)
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+/// Create a [`HostKeyStore`] of the secret key kept by the [`IdentityHandle`], encrypted by `pwd`.
+///
+/// It fails for the identities without a secret key, e.g. the anonymous or the delegated ones.
+#[no_mangle]
+pub extern "C" fn keystore_from_identity(
+    name: *const c_char,
+    pwd: *const c_char,
+    ptr_iden: *const IdentityHandle,
+    p2ptr: *mut *const HostKeyStore,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let once = || -> AnyResult<HostKeyStore> {
+        let name = unsafe { CStr::from_ptr(name).to_str() }?;
+        let pwd = unsafe { CStr::from_ptr(pwd).to_str() }?;
+        let identity = unsafe { IdentityHandle::from_ptr(ptr_iden) };
+
+        HostKeyStore::from_identity(name, pwd, identity)
+    };
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, once())
+}
+
+#[no_mangle]
+pub extern "C" fn keystore_from_json(
+    json: *const c_char,
+    p2ptr: *mut *const HostKeyStore,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let json = unsafe { CStr::from_ptr(json).to_str().map_err(AnyErr::from) };
+
+    let key_store = json.and_then(HostKeyStore::from_json);
+
+    __todo_replace_this_by_macro(p2ptr, err_cb, key_store)
+}
+
+#[no_mangle]
+pub extern "C" fn keystore_to_json(
+    ptr: *const HostKeyStore,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let key_store = unsafe { &*ptr };
+
+    let json = key_store
+        .to_json()
+        .and_then(|json| CString::new(json).map_err(AnyErr::from))
+        .map(|json| json.into_bytes_with_nul());
+
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, json)
+}
+
+#[no_mangle]
+pub extern "C" fn keystore_change_password(
+    ptr: *mut HostKeyStore,
+    old_pwd: *const c_char,
+    new_pwd: *const c_char,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let key_store = unsafe { &mut *ptr };
+
+    let mut once = || -> AnyResult<()> {
+        let old_pwd = unsafe { CStr::from_ptr(old_pwd).to_str() }?;
+        let new_pwd = unsafe { CStr::from_ptr(new_pwd).to_str() }?;
+
+        key_store.change_password(old_pwd, new_pwd)
+    };
+
+    match once() {
+        Ok(()) => StateCode::Ok,
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+/// Decrypt the [`HostKeyStore`] by `pwd` into an [`IdentityHandle`].
+#[no_mangle]
+pub extern "C" fn keystore_to_identity(
+    ptr: *const HostKeyStore,
+    pwd: *const c_char,
+    p2ptr_iden: *mut *const IdentityHandle,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let key_store = unsafe { &*ptr };
+
+    let handle = unsafe { CStr::from_ptr(pwd).to_str().map_err(AnyErr::from) }
+        .and_then(|pwd| key_store.to_identity(pwd));
+
+    crate::identity::__todo_replace_this_by_macro(p2ptr_iden, err_cb, handle)
+}
+
+/// Give the principal of the [`HostKeyStore`], which has been checked against its public key when
+/// loaded by [`keystore_from_json`].
+#[no_mangle]
+pub extern "C" fn keystore_principal(
+    ptr: *const HostKeyStore,
+    ret_cb: UnsizedCallBack<u8>,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let key_store = unsafe { &*ptr };
+
+    let principal: Result<_, String> = Ok(key_store.principal());
+
+    crate::principal::__todo_replace_this_by_macro(ret_cb, err_cb, principal)
+}
+
+/// Return the format version of the [`HostKeyStore`].
+#[no_mangle]
+pub extern "C" fn keystore_version(ptr: *const HostKeyStore) -> u32 {
+    let key_store = unsafe { &*ptr };
+
+    key_store.version()
+}
+
+#[no_mangle]
+pub extern "C" fn keystore_free(ptr: *const HostKeyStore) {
+    let boxed = unsafe { Box::from_raw(ptr as *mut HostKeyStore) };
+
+    drop(boxed);
+}
+
+fn __todo_replace_this_by_macro(
+    p2ptr: *mut *const HostKeyStore,
+    err_cb: UnsizedCallBack<u8>,
+    r: Result<HostKeyStore, impl Display>,
+) -> StateCode {
+    match r {
+        Ok(t) => {
+            unsafe {
+                ret_thin_ptr(p2ptr, t);
+            }
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::{
+        identity_anonymous, identity_free, identity_secp256k1_random, identity_type, IdentityType,
+    };
+    use crate::tests_util::{apply_ptr, empty_err_cb, panic_err_cb};
+    use libc::c_int;
+    use std::sync::Mutex;
+
+    const NAME: &[u8] = b"test account\0";
+    const PASSWORD: &[u8] = b"ABCDE123456abcde\0";
+    const NEW_PASSWORD: &[u8] = b"123456ABCDEabcde\0";
+
+    #[test]
+    fn keystore_create_should_work() {
+        let mut ptr = apply_ptr::<HostKeyStore>();
+
+        assert_eq!(
+            keystore_create(
+                NAME.as_ptr() as *const c_char,
+                PASSWORD.as_ptr() as *const c_char,
+                &mut ptr,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        // Free here!
+        let boxed = unsafe { Box::from_raw(ptr as *mut HostKeyStore) };
+        assert_eq!(boxed.meta.name, "test account");
+    }
+
+    #[test]
+    fn keystore_json_should_work() {
+        static JSON: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+        let mut ptr = apply_ptr::<HostKeyStore>();
+        keystore_create(
+            NAME.as_ptr() as *const c_char,
+            PASSWORD.as_ptr() as *const c_char,
+            &mut ptr,
+            panic_err_cb,
+        );
+
+        extern "C" fn ret_cb(data: *const u8, len: c_int) {
+            let slice = unsafe { std::slice::from_raw_parts(data, len as usize) };
+
+            *JSON.lock().unwrap() = slice.to_vec();
+        }
+
+        assert_eq!(keystore_to_json(ptr, ret_cb, panic_err_cb), StateCode::Ok);
+
+        let json = JSON.lock().unwrap().clone();
+        let mut ptr_infer = apply_ptr::<HostKeyStore>();
+        assert_eq!(
+            keystore_from_json(json.as_ptr() as *const c_char, &mut ptr_infer, panic_err_cb),
+            StateCode::Ok
+        );
+
+        unsafe {
+            assert_eq!(*ptr, *ptr_infer);
+        }
+        assert_eq!(keystore_version(ptr_infer), host_keystore::VERSION);
+
+        // Free here!
+        keystore_free(ptr);
+        keystore_free(ptr_infer);
+    }
+
+    #[test]
+    fn keystore_to_identity_should_work() {
+        let mut ptr = apply_ptr::<HostKeyStore>();
+        keystore_create(
+            NAME.as_ptr() as *const c_char,
+            PASSWORD.as_ptr() as *const c_char,
+            &mut ptr,
+            panic_err_cb,
+        );
+
+        assert_eq!(
+            keystore_change_password(
+                ptr as *mut HostKeyStore,
+                PASSWORD.as_ptr() as *const c_char,
+                NEW_PASSWORD.as_ptr() as *const c_char,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+
+        assert_eq!(
+            keystore_to_identity(
+                ptr,
+                PASSWORD.as_ptr() as *const c_char,
+                &mut ptr_iden,
+                empty_err_cb
+            ),
+            StateCode::Err
+        );
+        assert_eq!(
+            keystore_to_identity(
+                ptr,
+                NEW_PASSWORD.as_ptr() as *const c_char,
+                &mut ptr_iden,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        unsafe {
+            let handle = IdentityHandle::from_ptr(ptr_iden);
+            assert_eq!(handle.identity().sender(), Ok((*ptr).principal()));
+        }
+
+        // Free here!
+        identity_free(ptr_iden);
+        keystore_free(ptr);
+    }
+
+    #[test]
+    fn keystore_create_secp256k1_should_work() {
+        let mut ptr = apply_ptr::<HostKeyStore>();
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+
+        assert_eq!(
+            keystore_create_secp256k1(
+                NAME.as_ptr() as *const c_char,
+                PASSWORD.as_ptr() as *const c_char,
+                &mut ptr,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+        assert_eq!(
+            keystore_to_identity(
+                ptr,
+                PASSWORD.as_ptr() as *const c_char,
+                &mut ptr_iden,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        assert_eq!(identity_type(ptr_iden), IdentityType::Secp256K1);
+
+        // Free here!
+        identity_free(ptr_iden);
+        keystore_free(ptr);
+    }
+
+    #[test]
+    fn keystore_from_identity_should_work() {
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_secp256k1_random(&mut ptr_iden);
+
+        let mut ptr = apply_ptr::<HostKeyStore>();
+        assert_eq!(
+            keystore_from_identity(
+                NAME.as_ptr() as *const c_char,
+                PASSWORD.as_ptr() as *const c_char,
+                ptr_iden,
+                &mut ptr,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let mut ptr_iden_infer = apply_ptr::<IdentityHandle>();
+        assert_eq!(
+            keystore_to_identity(
+                ptr,
+                PASSWORD.as_ptr() as *const c_char,
+                &mut ptr_iden_infer,
+                panic_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        assert_eq!(identity_type(ptr_iden_infer), IdentityType::Secp256K1);
+        unsafe {
+            let sender = IdentityHandle::from_ptr(ptr_iden).identity().sender();
+            assert_eq!(sender, Ok((*ptr).principal()));
+            assert_eq!(
+                IdentityHandle::from_ptr(ptr_iden_infer).identity().sender(),
+                sender
+            );
+        }
+
+        // Free here!
+        identity_free(ptr_iden);
+        identity_free(ptr_iden_infer);
+        keystore_free(ptr);
+    }
+
+    #[test]
+    fn keystore_from_identity_should_fail() {
+        let mut ptr_iden = apply_ptr::<IdentityHandle>();
+        identity_anonymous(&mut ptr_iden);
+
+        let mut ptr = apply_ptr::<HostKeyStore>();
+        assert_eq!(
+            keystore_from_identity(
+                NAME.as_ptr() as *const c_char,
+                PASSWORD.as_ptr() as *const c_char,
+                ptr_iden,
+                &mut ptr,
+                empty_err_cb
+            ),
+            StateCode::Err
+        );
+
+        // Free here!
+        identity_free(ptr_iden);
+    }
+}
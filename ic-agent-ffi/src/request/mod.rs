@@ -0,0 +1,228 @@
+//! # Intro
+//!
+//! Poll-based handles of the calls running in the background, for the callers which can't be
+//! called back from a foreign thread.
+
+use crate::{ret_thin_ptr, ret_unsized, AnyResult, StateCode, UnsizedCallBack};
+use candid::IDLArgs;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+#[derive(Debug)]
+enum RequestState {
+    Pending,
+    Done(IDLArgs),
+    Failed(String),
+    Taken,
+}
+
+#[derive(Debug)]
+pub struct RequestHandle {
+    state: Arc<Mutex<RequestState>>,
+    task: JoinHandle<()>,
+}
+
+impl RequestHandle {
+    /// Run `future` on `runtime`, the outcome is kept in the handle until it is taken.
+    pub fn spawn<F>(runtime: &Runtime, future: F) -> Self
+    where
+        F: Future<Output = AnyResult<IDLArgs>> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(RequestState::Pending));
+
+        let task = runtime.spawn({
+            let state = state.clone();
+
+            async move {
+                let r = match future.await {
+                    Ok(idl_args) => RequestState::Done(idl_args),
+                    Err(e) => RequestState::Failed(e.to_string()),
+                };
+
+                *state.lock().unwrap() = r;
+            }
+        });
+
+        Self { state, task }
+    }
+
+    pub fn poll(&self) -> StateCode {
+        match *self.state.lock().unwrap() {
+            RequestState::Pending => StateCode::Pending,
+            RequestState::Done(_) | RequestState::Taken => StateCode::Ok,
+            RequestState::Failed(_) => StateCode::Err,
+        }
+    }
+
+    pub fn take(&self) -> Result<Option<IDLArgs>, String> {
+        let mut state = self.state.lock().unwrap();
+
+        match &*state {
+            RequestState::Pending => Ok(None),
+            RequestState::Done(_) => match std::mem::replace(&mut *state, RequestState::Taken) {
+                RequestState::Done(idl_args) => Ok(Some(idl_args)),
+                _ => unreachable!(),
+            },
+            RequestState::Failed(e) => Err(e.clone()),
+            RequestState::Taken => Err("The result of request has been taken".into()),
+        }
+    }
+}
+
+impl Drop for RequestHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Return the state of the request: [`StateCode::Pending`], [`StateCode::Ok`] or [`StateCode::Err`].
+#[no_mangle]
+pub extern "C" fn request_poll(ptr: *const RequestHandle) -> StateCode {
+    let request = unsafe { &*ptr };
+
+    request.poll()
+}
+
+/// Move the result out of a finished request.
+///
+/// Return [`StateCode::Pending`] without touching `p2ptr` if the request is still running.
+#[no_mangle]
+pub extern "C" fn request_take_result(
+    ptr: *const RequestHandle,
+    p2ptr: *mut *const IDLArgs,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let request = unsafe { &*ptr };
+
+    match request.take() {
+        Ok(Some(idl_args)) => {
+            unsafe {
+                ret_thin_ptr(p2ptr, idl_args);
+            }
+
+            StateCode::Ok
+        }
+        Ok(None) => StateCode::Pending,
+        Err(e) => {
+            ret_unsized(err_cb, e + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+/// Free the request, abort it if it is still running.
+#[no_mangle]
+pub extern "C" fn request_free(ptr: *const RequestHandle) {
+    let boxed = unsafe { Box::from_raw(ptr as *mut RequestHandle) };
+
+    drop(boxed);
+}
+
+pub(crate) fn __todo_replace_this_by_macro(
+    p2ptr: *mut *const RequestHandle,
+    err_cb: UnsizedCallBack<u8>,
+    r: Result<RequestHandle, impl Display>,
+) -> StateCode {
+    match r {
+        Ok(t) => {
+            unsafe {
+                ret_thin_ptr(p2ptr, t);
+            }
+
+            StateCode::Ok
+        }
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests_util::{apply_ptr, empty_err_cb};
+    use anyhow::anyhow;
+    use candid::parser::value::IDLValue;
+    use libc::c_int;
+    use std::ffi::CStr;
+    use std::time::Duration;
+
+    fn wait_for(request: &RequestHandle) -> StateCode {
+        loop {
+            match request_poll(request) {
+                StateCode::Pending => std::thread::sleep(Duration::from_millis(10)),
+                sc => return sc,
+            }
+        }
+    }
+
+    #[test]
+    fn request_take_result_should_work() {
+        let runtime = Runtime::new().unwrap();
+        let request = RequestHandle::spawn(&runtime, async {
+            Ok(IDLArgs::new(&[IDLValue::Bool(true)]))
+        });
+
+        assert_eq!(wait_for(&request), StateCode::Ok);
+
+        let mut ptr = apply_ptr::<IDLArgs>();
+
+        assert_eq!(
+            request_take_result(&request, &mut ptr, empty_err_cb),
+            StateCode::Ok
+        );
+        assert_eq!(
+            request_take_result(&request, &mut ptr, empty_err_cb),
+            StateCode::Err
+        );
+
+        let boxed = unsafe { Box::from_raw(ptr as *mut IDLArgs) };
+        assert_eq!(boxed.as_ref(), &IDLArgs::new(&[IDLValue::Bool(true)]));
+    }
+
+    #[test]
+    fn request_take_result_should_fail() {
+        extern "C" fn err_cb(data: *const u8, _len: c_int) {
+            let c_str = unsafe { CStr::from_ptr(data as *const i8) };
+            let str = c_str.to_str().unwrap();
+            assert_eq!(str, "failed on purpose");
+        }
+
+        let runtime = Runtime::new().unwrap();
+        let request = RequestHandle::spawn(&runtime, async { Err(anyhow!("failed on purpose")) });
+
+        assert_eq!(wait_for(&request), StateCode::Err);
+
+        let mut ptr = apply_ptr::<IDLArgs>();
+
+        assert_eq!(
+            request_take_result(&request, &mut ptr, err_cb),
+            StateCode::Err
+        );
+        assert!(ptr.is_null());
+    }
+
+    #[test]
+    fn request_free_should_work() {
+        let runtime = Runtime::new().unwrap();
+        let request = RequestHandle::spawn(&runtime, futures::future::pending());
+
+        assert_eq!(request_poll(&request), StateCode::Pending);
+
+        let mut ptr = apply_ptr::<IDLArgs>();
+
+        assert_eq!(
+            request_take_result(&request, &mut ptr, empty_err_cb),
+            StateCode::Pending
+        );
+        assert!(ptr.is_null());
+
+        request_free(Box::into_raw(Box::new(request)));
+    }
+}
@@ -0,0 +1,204 @@
+//! # Intro
+//!
+//! Verify the signatures produced by [`ic_agent::Identity`] or any other IC compatible signer.
+
+use crate::identity::{ED25519_DER_PREFIX, SECP256K1_DER_PREFIX};
+use crate::{ret_unsized, AnyErr, AnyResult, StateCode, UnsizedCallBack};
+use anyhow::{anyhow, bail};
+use ic_types::Principal;
+use k256::ecdsa::signature::Verifier;
+use libc::c_int;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, ED25519};
+
+/// The DER prefix of a P-256 public key, followed by the 65 bytes uncompressed point.
+const P256_DER_PREFIX: &[u8] = b"\x30\x59\x30\x13\x06\x07\x2a\x86\x48\xce\x3d\x02\x01\x06\x08\x2a\x86\x48\xce\x3d\x03\x01\x07\x03\x42\x00";
+
+/// The OID of IC canister signatures, `1.3.6.1.4.1.56387.1.2`.
+const CANISTER_SIG_OID: &[u8] = b"\x06\x0a\x2b\x06\x01\x04\x01\x83\xb8\x43\x01\x02";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyAlgorithm {
+    Ed25519,
+    Secp256k1,
+    P256,
+    CanisterSig,
+}
+
+impl KeyAlgorithm {
+    /// Detect the algorithm from the DER encoded public key, and give the raw key.
+    pub fn detect(public_key: &[u8]) -> AnyResult<(Self, &[u8])> {
+        let prefixes = [
+            (Self::Ed25519, ED25519_DER_PREFIX),
+            (Self::Secp256k1, SECP256K1_DER_PREFIX),
+            (Self::P256, P256_DER_PREFIX),
+        ];
+
+        for (algorithm, prefix) in prefixes {
+            if let Some(raw) = public_key.strip_prefix(prefix) {
+                return Ok((algorithm, raw));
+            }
+        }
+
+        if public_key
+            .windows(CANISTER_SIG_OID.len())
+            .any(|window| window == CANISTER_SIG_OID)
+        {
+            return Ok((Self::CanisterSig, public_key));
+        }
+
+        bail!("The algorithm of public key is unknown")
+    }
+}
+
+/// Verify the `signature` of `message` by the DER encoded `public_key`.
+pub fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> AnyResult<()> {
+    let (algorithm, raw) = KeyAlgorithm::detect(public_key)?;
+
+    match algorithm {
+        KeyAlgorithm::Ed25519 => UnparsedPublicKey::new(&ED25519, raw)
+            .verify(message, signature)
+            .map_err(|_| anyhow!("The signature is invalid")),
+        KeyAlgorithm::P256 => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, raw)
+            .verify(message, signature)
+            .map_err(|_| anyhow!("The signature is invalid")),
+        KeyAlgorithm::Secp256k1 => {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(raw)?;
+            let signature = k256::ecdsa::Signature::try_from(signature)?;
+
+            key.verify(message, &signature)
+                .map_err(|_| anyhow!("The signature is invalid"))
+        }
+        KeyAlgorithm::CanisterSig => {
+            bail!("The canister signatures can't be verified without the root key of IC")
+        }
+    }
+}
+
+/// Verify the `signature` of `message` by the DER encoded `public_key`, the algorithm is detected
+/// from the key: Ed25519, Secp256k1 or P-256.
+///
+/// If `principal` is not null, the key has to be the one of the self-authenticating principal.
+///
+/// # Arguments
+///
+/// * `principal` - The bytes of [`Principal`], can be null.
+/// * `principal_len` - The length of `principal`.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn signature_verify(
+    public_key: *const u8,
+    public_key_len: c_int,
+    message: *const u8,
+    message_len: c_int,
+    signature: *const u8,
+    signature_len: c_int,
+    principal: *const u8,
+    principal_len: c_int,
+    err_cb: UnsizedCallBack<u8>,
+) -> StateCode {
+    let public_key = unsafe { std::slice::from_raw_parts(public_key, public_key_len as usize) };
+    let message = unsafe { std::slice::from_raw_parts(message, message_len as usize) };
+    let signature = unsafe { std::slice::from_raw_parts(signature, signature_len as usize) };
+
+    let once = || -> AnyResult<()> {
+        if !principal.is_null() {
+            let principal =
+                unsafe { std::slice::from_raw_parts(principal, principal_len as usize) };
+            let principal = Principal::try_from_slice(principal).map_err(AnyErr::from)?;
+
+            if principal != Principal::self_authenticating(public_key) {
+                bail!("The public key doesn't belong to {}", principal);
+            }
+        }
+
+        verify(public_key, message, signature)
+    };
+
+    match once() {
+        Ok(()) => StateCode::Ok,
+        Err(e) => {
+            ret_unsized(err_cb, e.to_string() + "\0");
+
+            StateCode::Err
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests_util::{empty_err_cb, BASIC_IDENTITY_FILE};
+    use ic_agent::identity::{BasicIdentity, Secp256k1Identity};
+    use ic_agent::Identity;
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+
+    const MESSAGE: &[u8] = b"challenge";
+
+    #[test]
+    fn verify_should_work() {
+        let basic = BasicIdentity::from_pem(BASIC_IDENTITY_FILE.as_bytes()).unwrap();
+        let secp256k1 = Secp256k1Identity::from_private_key(SecretKey::random(OsRng));
+
+        for identity in [&basic as &dyn Identity, &secp256k1] {
+            let signature = identity.sign(MESSAGE).unwrap();
+            let public_key = signature.public_key.unwrap();
+            let signature = signature.signature.unwrap();
+
+            assert!(verify(&public_key, MESSAGE, &signature).is_ok());
+            assert!(verify(&public_key, b"another", &signature).is_err());
+        }
+    }
+
+    #[test]
+    fn key_algorithm_detect_should_work() {
+        const CANISTER_SIG_KEY: &[u8] =
+            b"\x30\x1d\x30\x0c\x06\x0a\x2b\x06\x01\x04\x01\x83\xb8\x43\x01\x02\x03\x0d\x00\x0a\x00\x00\x00\x00\x00\x00\x00\x07\x01\x01";
+
+        assert_eq!(
+            KeyAlgorithm::detect(CANISTER_SIG_KEY).unwrap().0,
+            KeyAlgorithm::CanisterSig
+        );
+        assert!(KeyAlgorithm::detect(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn signature_verify_should_work() {
+        let basic = BasicIdentity::from_pem(BASIC_IDENTITY_FILE.as_bytes()).unwrap();
+        let principal = basic.sender().unwrap();
+        let signature = basic.sign(MESSAGE).unwrap();
+        let public_key = signature.public_key.unwrap();
+        let signature = signature.signature.unwrap();
+
+        assert_eq!(
+            signature_verify(
+                public_key.as_ptr(),
+                public_key.len() as c_int,
+                MESSAGE.as_ptr(),
+                MESSAGE.len() as c_int,
+                signature.as_ptr(),
+                signature.len() as c_int,
+                principal.as_slice().as_ptr(),
+                principal.as_slice().len() as c_int,
+                empty_err_cb
+            ),
+            StateCode::Ok
+        );
+
+        let anonymous = Principal::anonymous();
+        assert_eq!(
+            signature_verify(
+                public_key.as_ptr(),
+                public_key.len() as c_int,
+                MESSAGE.as_ptr(),
+                MESSAGE.len() as c_int,
+                signature.as_ptr(),
+                signature.len() as c_int,
+                anonymous.as_slice().as_ptr(),
+                anonymous.as_slice().len() as c_int,
+                empty_err_cb
+            ),
+            StateCode::Err
+        );
+    }
+}
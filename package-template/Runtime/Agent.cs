@@ -2,13 +2,35 @@
 
 #nullable enable
 using System;
+using System.Collections.Concurrent;
 using System.Runtime.InteropServices;
+using System.Threading;
+using System.Threading.Tasks;
 using Candid;
 
 public class Agent
 {
     private IntPtr _ptr;
 
+    // The calls without blocking, completed by the callbacks from a background thread of rust.
+    private static readonly ConcurrentDictionary<UInt64, TaskCompletionSource<IDLArgs>> _inFlight =
+        new ConcurrentDictionary<UInt64, TaskCompletionSource<IDLArgs>>();
+
+    // Kept alive for all the calls, they're called after the calling function returns.
+    private static readonly AsyncRetCallback _asyncRetCb = (requestId, ptr) =>
+    {
+        InFlight(requestId).TrySetResult(new IDLArgs(ptr));
+    };
+    private static readonly AsyncErrCallback _asyncErrCb = (requestId, data, len) =>
+    {
+        var error = Marshal.PtrToStringAnsi(data);
+
+        if (error == null)
+            InFlight(requestId).TrySetException(new FailedCallingRust("Failed on getting error from rust."));
+        else
+            InFlight(requestId).TrySetException(new ErrorFromRust(error));
+    };
+
     private Agent(IntPtr ptr)
     {
         _ptr = ptr;
@@ -19,11 +41,14 @@ public class Agent
         FromRust.agent_free(_ptr);
     }
 
+    /// <summary>
+    /// Create an agent of the canister, `didContent` can be null to make only the raw calls.
+    /// </summary>
     public static Agent Create(
         string url,
         Identity identity,
         Principal canisterId,
-        string didContent
+        string? didContent
     )
     {
         string? outError = null;
@@ -34,11 +59,50 @@ public class Agent
 
         var sc = FromRust.agent_create(
             url,
-            identity._p2FPtr,
-            identity.Type,
+            identity._ptr,
+            canisterId.Bytes,
+            canisterId.Bytes.Length,
+            didContent,
+            out IntPtr ptr,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+            return new Agent(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an agent of the canister whose calls follow `config`, e.g. to fetch the root key of
+    /// a local network. `didContent` can be null to make only the raw calls.
+    /// </summary>
+    public static Agent CreateWithConfig(
+        string url,
+        Identity identity,
+        Principal canisterId,
+        string? didContent,
+        AgentConfig config
+    )
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_create_with_config(
+            url,
+            identity._ptr,
             canisterId.Bytes,
             canisterId.Bytes.Length,
             didContent,
+            ref config,
             out IntPtr ptr,
             errCb
         );
@@ -103,7 +167,420 @@ public class Agent
         
         throw new ErrorFromRust(outError);
     }
+
+    /// <summary>
+    /// Call a query method without blocking, the call is aborted if `cancellationToken` is
+    /// cancelled before the result comes.
+    /// </summary>
+    public Task<IDLArgs> QueryAsync(string funcName, IDLArgs args, CancellationToken cancellationToken = default)
+    {
+        var requestId = FromRust.agent_query_async(
+            this._ptr,
+            funcName,
+            args.ToString(),
+            _asyncRetCb,
+            _asyncErrCb
+        );
+
+        return Track(requestId, cancellationToken);
+    }
+
+    /// <summary>
+    /// Call an update method without blocking, the call is aborted if `cancellationToken` is
+    /// cancelled before the result comes.
+    /// </summary>
+    public Task<IDLArgs> UpdateAsync(string funcName, IDLArgs args, CancellationToken cancellationToken = default)
+    {
+        var requestId = FromRust.agent_update_async(
+            this._ptr,
+            funcName,
+            args.ToString(),
+            _asyncRetCb,
+            _asyncErrCb
+        );
+
+        return Track(requestId, cancellationToken);
+    }
+
+    // Abort a call made by `QueryAsync` or `UpdateAsync`, gives false if it has already finished.
+    private static bool Cancel(UInt64 requestId)
+    {
+        if (!FromRust.agent_cancel(requestId))
+            return false;
+
+        InFlight(requestId).TrySetCanceled();
+
+        return true;
+    }
+
+    // The callback may come before the caller gets the request id, so both sides add the entry.
+    private static TaskCompletionSource<IDLArgs> InFlight(UInt64 requestId)
+    {
+        return _inFlight.GetOrAdd(
+            requestId,
+            _ => new TaskCompletionSource<IDLArgs>(TaskCreationOptions.RunContinuationsAsynchronously)
+        );
+    }
+
+    private static Task<IDLArgs> Track(UInt64 requestId, CancellationToken cancellationToken)
+    {
+        var task = InFlight(requestId).Task;
+
+        var registration = cancellationToken.Register(() => Cancel(requestId));
+        task.ContinueWith(_ =>
+        {
+            registration.Dispose();
+            _inFlight.TryRemove(requestId, out var _);
+        });
+
+        return task;
+    }
+
+    /// <summary>
+    /// Call a query method without blocking, gives a `Request` to poll the result from.
+    /// </summary>
+    public Request QueryBegin(string funcName, IDLArgs args)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_query_begin(
+            this._ptr,
+            funcName,
+            args.ToString(),
+            out IntPtr ptr,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+            return new Request(ptr);
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Call an update method without blocking, gives a `Request` to poll the result from.
+    /// </summary>
+    public Request UpdateBegin(string funcName, IDLArgs args)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_update_begin(
+            this._ptr,
+            funcName,
+            args.ToString(),
+            out IntPtr ptr,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+            return new Request(ptr);
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Call a query method with the candid encoded args, e.g. `IDLArgs.ToBytes`, gives the
+    /// encoded reply.
+    /// </summary>
+    public byte[] QueryRaw(string funcName, byte[] args)
+    {
+        byte[]? outReply = null;
+        string? outError = null;
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outReply = new byte[len];
+            Marshal.Copy(data, outReply, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_query_raw(
+            this._ptr,
+            funcName,
+            args,
+            args.Length,
+            retCb,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+        {
+            if (outReply == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+
+            return outReply;
+        }
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Call an update method with the candid encoded args, e.g. `IDLArgs.ToBytes`, gives the
+    /// encoded reply.
+    /// </summary>
+    public byte[] UpdateRaw(string funcName, byte[] args)
+    {
+        byte[]? outReply = null;
+        string? outError = null;
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outReply = new byte[len];
+            Marshal.Copy(data, outReply, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_update_raw(
+            this._ptr,
+            funcName,
+            args,
+            args.Length,
+            retCb,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+        {
+            if (outReply == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+
+            return outReply;
+        }
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
     
+    /// <summary>
+    /// The argument and return types of the method in the candid of this agent.
+    /// </summary>
+    public (IDLType[], IDLType[]) MethodTypes(string funcName)
+    {
+        IDLType[]? outArgs = null;
+        IDLType[]? outRets = null;
+        string? outError = null;
+        UnsizedCallback argsCb = (data, len) =>
+        {
+            outArgs = IDLType.FromPtrs(data, len);
+        };
+        UnsizedCallback retsCb = (data, len) =>
+        {
+            outRets = IDLType.FromPtrs(data, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_method_types(
+            this._ptr,
+            funcName,
+            argsCb,
+            retsCb,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+        {
+            if (outArgs == null || outRets == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+
+            return (outArgs, outRets);
+        }
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Sign a query call without sending it, gives the CBOR encoded envelope.
+    /// </summary>
+    public byte[] SignQuery(string funcName, IDLArgs args)
+    {
+        byte[]? outEnvelope = null;
+        string? outError = null;
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outEnvelope = new byte[len];
+            Marshal.Copy(data, outEnvelope, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_sign_query(
+            this._ptr,
+            funcName,
+            args.ToString(),
+            retCb,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+        {
+            if (outEnvelope == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+
+            return outEnvelope;
+        }
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Sign an update call without sending it, gives the CBOR encoded envelope and its request id.
+    /// </summary>
+    public (byte[], byte[]) SignUpdate(string funcName, IDLArgs args)
+    {
+        byte[]? outEnvelope = null;
+        byte[]? outRequestId = null;
+        string? outError = null;
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outEnvelope = new byte[len];
+            Marshal.Copy(data, outEnvelope, 0, len);
+        };
+        UnsizedCallback requestIdCb = (data, len) =>
+        {
+            outRequestId = new byte[len];
+            Marshal.Copy(data, outRequestId, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_sign_update(
+            this._ptr,
+            funcName,
+            args.ToString(),
+            retCb,
+            requestIdCb,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+        {
+            if (outEnvelope == null || outRequestId == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+
+            return (outEnvelope, outRequestId);
+        }
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Send a query envelope given by `SignQuery`.
+    /// </summary>
+    public IDLArgs SubmitQuery(byte[] envelope)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_submit_query(
+            this._ptr,
+            envelope,
+            envelope.Length,
+            out IntPtr ptr,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+            return new IDLArgs(ptr);
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Send an update envelope given by `SignUpdate` and wait for its certified reply.
+    /// </summary>
+    public IDLArgs SubmitUpdate(byte[] envelope)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_submit_update(
+            this._ptr,
+            envelope,
+            envelope.Length,
+            out IntPtr ptr,
+            errCb
+        );
+
+        if (sc == StateCode.Ok)
+            return new IDLArgs(ptr);
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    /// <summary>
+    /// Pin the root key used to verify the certificates from the replica, e.g. of a testnet. It's
+    /// kept even if the config fetches the root key.
+    /// </summary>
+    public void SetRootKey(byte[] rootKey)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.agent_set_root_key(this._ptr, rootKey, rootKey.Length, errCb);
+
+        if (sc != StateCode.Ok)
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
     public string Status()
     {
         string? outIdlArgs = null;
@@ -144,11 +621,22 @@ public class Agent
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode agent_create(
             [MarshalAs(UnmanagedType.LPStr)] string url,
-            IntPtr[] fptr2Identity,
-            IdentityType identityType,
+            IntPtr ptr2Identity,
             byte[] canisterIdBytes,
             Int32 canisterIdBytesLen,
-            [MarshalAs(UnmanagedType.LPStr)] string didContent,
+            [MarshalAs(UnmanagedType.LPStr)] string? didContent,
+            out IntPtr ptr2Agent,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_create_with_config(
+            [MarshalAs(UnmanagedType.LPStr)] string url,
+            IntPtr ptr2Identity,
+            byte[] canisterIdBytes,
+            Int32 canisterIdBytesLen,
+            [MarshalAs(UnmanagedType.LPStr)] string? didContent,
+            ref AgentConfig config,
             out IntPtr ptr2Agent,
             UnsizedCallback errCb
         );
@@ -171,6 +659,120 @@ public class Agent
             UnsizedCallback errCb
         );
 
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern UInt64 agent_query_async(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            [MarshalAs(UnmanagedType.LPStr)] string funcArgs,
+            AsyncRetCallback retCb,
+            AsyncErrCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern UInt64 agent_update_async(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            [MarshalAs(UnmanagedType.LPStr)] string funcArgs,
+            AsyncRetCallback retCb,
+            AsyncErrCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        internal static extern bool agent_cancel(UInt64 requestId);
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_query_begin(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            [MarshalAs(UnmanagedType.LPStr)] string funcArgs,
+            out IntPtr ptr2Request,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_update_begin(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            [MarshalAs(UnmanagedType.LPStr)] string funcArgs,
+            out IntPtr ptr2Request,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_query_raw(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            byte[] args,
+            Int32 argsLen,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_update_raw(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            byte[] args,
+            Int32 argsLen,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_method_types(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            UnsizedCallback argsCb,
+            UnsizedCallback retsCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_sign_query(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            [MarshalAs(UnmanagedType.LPStr)] string funcArgs,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_sign_update(
+            IntPtr ptr2Agent,
+            [MarshalAs(UnmanagedType.LPStr)] string funcName,
+            [MarshalAs(UnmanagedType.LPStr)] string funcArgs,
+            UnsizedCallback retCb,
+            UnsizedCallback requestIdCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_submit_query(
+            IntPtr ptr2Agent,
+            byte[] envelope,
+            Int32 envelopeLen,
+            out IntPtr ptr2Args,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_submit_update(
+            IntPtr ptr2Agent,
+            byte[] envelope,
+            Int32 envelopeLen,
+            out IntPtr ptr2Args,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode agent_set_root_key(
+            IntPtr ptr2Agent,
+            byte[] rootKeyBytes,
+            Int32 rootKeyBytesLen,
+            UnsizedCallback errCb
+        );
+
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode agent_status(
             IntPtr ptr2Agent,
@@ -184,4 +786,43 @@ public class Agent
         );
     }
 }
-#nullable disable
\ No newline at end of file
+
+/// <summary>
+/// The configuration of an agent, applied to every call on it. Start from `AgentConfig.Default()`
+/// and modify the fields you need.
+/// </summary>
+[StructLayout(LayoutKind.Sequential)]
+public struct AgentConfig
+{
+    /// <summary>The lifetime(in seconds) of a request before the replica refuses it.</summary>
+    public UInt64 IngressExpirySecs;
+    /// <summary>The first interval(in milliseconds) between two polls of an update call.</summary>
+    public UInt64 PollingIntervalMs;
+    /// <summary>The factor by which the polling interval grows after each poll.</summary>
+    public float PollingBackoff;
+    /// <summary>The upper bound(in milliseconds) of the polling interval.</summary>
+    public UInt64 PollingMaxIntervalMs;
+    /// <summary>The time(in seconds) to wait for an update call before giving up.</summary>
+    public UInt64 TimeoutSecs;
+    /// <summary>Trust the root key served by the replica, only for a local/dev network.</summary>
+    [MarshalAs(UnmanagedType.I1)]
+    public bool FetchRootKey;
+
+    public static AgentConfig Default()
+    {
+        FromRust.agent_config_default(out AgentConfig config);
+
+        return config;
+    }
+
+    internal static class FromRust
+    {
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void agent_config_default(out AgentConfig config);
+    }
+}
+
+internal delegate void AsyncRetCallback(UInt64 requestId, IntPtr ptr2Args);
+
+internal delegate void AsyncErrCallback(UInt64 requestId, IntPtr data, Int32 len);
+#nullable disable
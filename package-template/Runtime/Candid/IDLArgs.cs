@@ -143,6 +143,94 @@ public class IDLArgs
             return outValues;
     }
 
+    /// <summary>
+    /// Annotate the values with the types, e.g. the return types given by `Agent.MethodTypes`, so
+    /// that the records and variants carry the field names of the candid.
+    /// </summary>
+    public IDLArgs Annotate(IDLType[] types)
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var ptrs = types.Select(type => type._ptr).ToArray();
+        var sc = FromRust.idl_args_annotate(_ptr, ptrs, ptrs.Length, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLArgs(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Parse a JSON array of the values typed by `types`, e.g. the argument types given by
+    /// `Agent.MethodTypes`. The 64-bit and the unbounded numbers are strings, blobs are base64
+    /// or hex prefixed by `0x`, variants are `{"tag": value}` and `null` is the absent opt.
+    /// </summary>
+    public static IDLArgs FromJson(string json, IDLType[] types)
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var ptrs = types.Select(type => type._ptr).ToArray();
+        var sc = FromRust.idl_args_from_json(json, ptrs, ptrs.Length, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLArgs(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Convert the values to a JSON array, in the same way as `FromJson`.
+    /// </summary>
+    public string ToJson(IDLType[] types)
+    {
+        string? outJson = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outJson = Marshal.PtrToStringAnsi(data);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var ptrs = types.Select(type => type._ptr).ToArray();
+        var sc = FromRust.idl_args_to_json(_ptr, ptrs, ptrs.Length, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outJson == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outJson;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
     internal static class FromRust
     {
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
@@ -186,6 +274,33 @@ public class IDLArgs
             UnsizedCallback retCb
         );
 
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_args_annotate(
+            IntPtr ptr2Args,
+            IntPtr[] p2ArrPtr,
+            Int32 arrLen,
+            out IntPtr ptr2Annotated,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_args_to_json(
+            IntPtr ptr2Args,
+            IntPtr[] p2ArrPtr,
+            Int32 arrLen,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_args_from_json(
+            [MarshalAs(UnmanagedType.LPStr)] string json,
+            IntPtr[] p2ArrPtr,
+            Int32 arrLen,
+            out IntPtr ptr2Args,
+            UnsizedCallback errCb
+        );
+
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern void idl_args_free(IntPtr ptr2Args);
     }
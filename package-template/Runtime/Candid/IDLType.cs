@@ -0,0 +1,369 @@
+using System;
+using System.Collections.Generic;
+using System.Runtime.InteropServices;
+
+namespace Candid
+{
+#nullable enable
+public class IDLType
+{
+    internal IntPtr _ptr;
+
+    public IDLTypeKind Kind => FromRust.idl_type_kind(_ptr);
+
+    internal IDLType(IntPtr ptr)
+    {
+        _ptr = ptr;
+    }
+
+    ~IDLType()
+    {
+        FromRust.idl_type_free(_ptr);
+    }
+
+    internal static IDLType[] FromPtrs(IntPtr data, Int32 len)
+    {
+        IntPtr[] ptrs = new IntPtr[len];
+        Marshal.Copy(data, ptrs, 0, len);
+
+        var types = new IDLType[len];
+        for (int i = 0; i < len; i++)
+            types[i] = new IDLType(ptrs[i]);
+
+        return types;
+    }
+
+    /// <summary>
+    /// Parse the type from text, the named types in it are looked up in `didContent` if given.
+    /// </summary>
+    public static IDLType FromText(string text, string? didContent = null)
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var sc = FromRust.idl_type_from_text(text, didContent, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLType(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// The service defined by the candid file.
+    /// </summary>
+    public static IDLType FromDid(string didContent)
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var sc = FromRust.idl_type_from_did(didContent, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLType(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public override string ToString()
+    {
+        string? outText = null;
+
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outText = Marshal.PtrToStringAnsi(data);
+        };
+        FromRust.idl_type_to_text(_ptr, retCb);
+
+        if (outText == null)
+            throw new FailedCallingRust("Failed on calling function of rust.");
+        else
+            return outText;
+    }
+
+    public IDLType AsOpt()
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var sc = FromRust.idl_type_as_opt(_ptr, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLType(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public IDLType AsVec()
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var sc = FromRust.idl_type_as_vec(_ptr, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLType(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public Dictionary<String, IDLType> AsRecord()
+    {
+        return AsNamed(FromRust.idl_type_as_record);
+    }
+
+    public Dictionary<String, IDLType> AsVariant()
+    {
+        return AsNamed(FromRust.idl_type_as_variant);
+    }
+
+    /// <summary>
+    /// The methods of the service, each of them is a `func` type.
+    /// </summary>
+    public Dictionary<String, IDLType> AsService()
+    {
+        return AsNamed(FromRust.idl_type_as_service);
+    }
+
+    public (IDLType[], IDLType[], IDLFuncMode) AsFunc()
+    {
+        IDLType[]? outArgs = null;
+        IDLType[]? outRets = null;
+        string? outError = null;
+
+        UnsizedCallback argsCb = (data, len) =>
+        {
+            outArgs = FromPtrs(data, len);
+        };
+        UnsizedCallback retsCb = (data, len) =>
+        {
+            outRets = FromPtrs(data, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var sc = FromRust.idl_type_as_func(_ptr, argsCb, retsCb, out IDLFuncMode mode, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outArgs == null || outRets == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return (outArgs, outRets, mode);
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    private delegate StateCode AsNamedFn(
+        IntPtr ptr2Type,
+        UnsizedCallback namesCb,
+        UnsizedCallback typesCb,
+        UnsizedCallback errCb
+    );
+
+    private Dictionary<String, IDLType> AsNamed(AsNamedFn asNamed)
+    {
+        Dictionary<String, IDLType> named = new Dictionary<string, IDLType>();
+        String[]? names = null;
+        IDLType[]? types = null;
+        string? outError = null;
+
+        UnsizedCallback namesCb = (data, len) =>
+        {
+            names = new string[len];
+
+            IntPtr[] namePtrs = new IntPtr[len];
+            Marshal.Copy(data, namePtrs, 0, len);
+
+            for (int i = 0; i < len; i++)
+            {
+                var str = Marshal.PtrToStringAnsi(namePtrs[i]);
+                if (str == null)
+                    throw new FailedCallingRust("Failed on calling function of rust.");
+                else
+                    names[i] = str;
+            }
+        };
+        UnsizedCallback typesCb = (data, len) =>
+        {
+            types = FromPtrs(data, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        var sc = asNamed(_ptr, namesCb, typesCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (names == null || types == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+
+            for (int i = 0; i < names.Length; i++)
+                named.Add(names[i], types[i]);
+
+            return named;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    internal static class FromRust
+    {
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_from_text(
+            [MarshalAs(UnmanagedType.LPStr)] string text,
+            [MarshalAs(UnmanagedType.LPStr)] string? didContent,
+            out IntPtr ptr2Type,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_from_did(
+            [MarshalAs(UnmanagedType.LPStr)] string didContent,
+            out IntPtr ptr2Type,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void idl_type_to_text(
+            IntPtr ptr2Type,
+            UnsizedCallback retCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern IDLTypeKind idl_type_kind(IntPtr ptr2Type);
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_as_opt(
+            IntPtr ptr2Type,
+            out IntPtr ptr2Inner,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_as_vec(
+            IntPtr ptr2Type,
+            out IntPtr ptr2Inner,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_as_record(
+            IntPtr ptr2Type,
+            UnsizedCallback idsCb,
+            UnsizedCallback typesCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_as_variant(
+            IntPtr ptr2Type,
+            UnsizedCallback idsCb,
+            UnsizedCallback typesCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_as_service(
+            IntPtr ptr2Type,
+            UnsizedCallback namesCb,
+            UnsizedCallback funcsCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_type_as_func(
+            IntPtr ptr2Type,
+            UnsizedCallback argsCb,
+            UnsizedCallback retsCb,
+            out IDLFuncMode mode,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void idl_type_free(IntPtr ptr2Type);
+    }
+}
+
+public enum IDLTypeKind
+{
+    Unknown = -1,
+    Null = 0,
+    Bool = 1,
+    Nat = 2,
+    Int = 3,
+    Nat8 = 4,
+    Nat16 = 5,
+    Nat32 = 6,
+    Nat64 = 7,
+    Int8 = 8,
+    Int16 = 9,
+    Int32 = 10,
+    Int64 = 11,
+    Float32 = 12,
+    Float64 = 13,
+    Text = 14,
+    Reserved = 15,
+    Empty = 16,
+    Principal = 17,
+    Opt = 18,
+    Vec = 19,
+    Record = 20,
+    Variant = 21,
+    Func = 22,
+    Service = 23,
+}
+
+public enum IDLFuncMode
+{
+    Update = 0,
+    Query = 1,
+    Oneway = 2,
+}
+#nullable disable
+}
@@ -143,6 +143,31 @@ public class IDLValue : IEquatable<IDLValue>
         }
     }
 
+    public static IDLValue WithRecord(Dictionary<UInt32, IDLValue> records)
+    {
+        string? outError = null;
+
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        UInt32[] ids = records.Keys.ToArray();
+        IntPtr[] vals = records.Values.Select(value => value._ptr).ToArray();
+
+        var sc = FromRust.idl_value_ct_record_with_ids(ids, ids.Length, vals, vals.Length, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLValue(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
     public static IDLValue WithVariant(string key, IDLValue value, UInt64 index)
     {
         string? outError = null;
@@ -1100,6 +1125,16 @@ public class IDLValue : IEquatable<IDLValue>
             UnsizedCallback errCb
         );
 
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode idl_value_ct_record_with_ids(
+            UInt32[] ids,
+            Int32 idsLen,
+            IntPtr[] vals,
+            Int32 valsLen,
+            out IntPtr ptr2Value,
+            UnsizedCallback errCb
+        );
+
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode idl_value_ct_variant(
             [MarshalAs(UnmanagedType.LPStr)] string key,
@@ -0,0 +1,53 @@
+using System;
+using System.Runtime.InteropServices;
+
+#nullable enable
+public static class Codegen
+{
+    /// <summary>
+    /// Generate the C# source of the service defined by the candid file: a class for every record
+    /// and variant, and an actor class named `className` which calls every method through `Agent`.
+    /// </summary>
+    public static string CSharp(string didContent, string className)
+    {
+        string? outSource = null;
+        string? outError = null;
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outSource = Marshal.PtrToStringAnsi(data);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.codegen_csharp(didContent, className, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outSource == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outSource;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    internal static class FromRust
+    {
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode codegen_csharp(
+            [MarshalAs(UnmanagedType.LPStr)] string didContent,
+            [MarshalAs(UnmanagedType.LPStr)] string className,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+    }
+}
+#nullable disable
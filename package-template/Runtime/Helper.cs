@@ -4,6 +4,7 @@ public enum StateCode
 {
     Ok = 0,
     Err = -1,
+    Pending = 1,
 }
 
 public class FailedCallingRust : Exception
@@ -4,19 +4,21 @@ using System.Runtime.InteropServices;
 #nullable enable
 public class Identity
 {
-    internal IntPtr[] _p2FPtr;
+    internal IntPtr _ptr;
 
-    public IdentityType Type { get; }
+    // Kept alive as long as the identity, rust calls it on every signing.
+    private ExternalSignCallback? _signCb;
 
-    private Identity(IntPtr[] p2FPtr, IdentityType type)
+    public IdentityType Type => FromRust.identity_type(_ptr);
+
+    internal Identity(IntPtr ptr)
     {
-        this._p2FPtr = p2FPtr;
-        this.Type = type;
+        this._ptr = ptr;
     }
 
     ~Identity()
     {
-        FromRust.identity_free(_p2FPtr);
+        FromRust.identity_free(_ptr);
     }
 
     /// <summary>
@@ -24,10 +26,9 @@ public class Identity
     /// </summary>
     public static Identity Anonymous()
     {
-        var p2FPtr = new IntPtr[2];
-        FromRust.identity_anonymous(p2FPtr);
+        FromRust.identity_anonymous(out IntPtr ptr);
 
-        return new Identity(p2FPtr, IdentityType.Anonymous);
+        return new Identity(ptr);
     }
 
     /// <summary>
@@ -41,12 +42,11 @@ public class Identity
             outError = Marshal.PtrToStringAnsi(data);
         };
 
-        var p2FPtr = new IntPtr[2];
 
-        var sc = FromRust.identity_basic_random(p2FPtr, errCb);
+        var sc = FromRust.identity_basic_random(out IntPtr ptr, errCb);
 
         if (sc == StateCode.Ok)
-            return new Identity(p2FPtr, IdentityType.Basic);
+            return new Identity(ptr);
         else
         {
             if (outError == null)
@@ -64,12 +64,11 @@ public class Identity
             outError = Marshal.PtrToStringAnsi(data);
         };
 
-        var p2FPtr = new IntPtr[2];
 
-        var sc = FromRust.identity_basic_from_pem(pem, p2FPtr, errCb);
+        var sc = FromRust.identity_basic_from_pem(pem, out IntPtr ptr, errCb);
 
         if (sc == StateCode.Ok)
-            return new Identity(p2FPtr, IdentityType.Basic);
+            return new Identity(ptr);
         else
         {
             if (outError == null)
@@ -84,10 +83,9 @@ public class Identity
     /// </summary>
     public static Identity Secp256K1Random()
     {
-        var p2FPtr = new IntPtr[2];
-        FromRust.identity_secp256k1_random(p2FPtr);
+        FromRust.identity_secp256k1_random(out IntPtr ptr);
 
-        return new Identity(p2FPtr, IdentityType.Secp256K1);
+        return new Identity(ptr);
     }
 
     public static Identity Secp256K1FromPem(string pem)
@@ -98,12 +96,317 @@ public class Identity
             outError = Marshal.PtrToStringAnsi(data);
         };
 
-        var p2FPtr = new IntPtr[2];
 
-        var sc = FromRust.identity_secp256k1_from_pem(pem, p2FPtr, errCb);
+        var sc = FromRust.identity_secp256k1_from_pem(pem, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `Basic` type from the raw 32 bytes seed of Ed25519.
+    /// </summary>
+    public static Identity BasicFromSecretKey(byte[] secretKey)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_basic_from_secret_key(secretKey, secretKey.Length, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `Secp256K1` type from the raw 32 bytes secret key.
+    /// </summary>
+    public static Identity Secp256K1FromSecretKey(byte[] secretKey)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_secp256k1_from_secret_key(secretKey, secretKey.Length, out IntPtr ptr, errCb);
 
         if (sc == StateCode.Ok)
-            return new Identity(p2FPtr, IdentityType.Secp256K1);
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Generate an English BIP-39 mnemonic of 12, 15, 18, 21 or 24 words.
+    /// </summary>
+    public static string GenerateMnemonic(UInt32 wordCount = 24)
+    {
+        string? outPhrase = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) => { outPhrase = Marshal.PtrToStringAnsi(data); };
+        UnsizedCallback errCb = (data, len) => { outError = Marshal.PtrToStringAnsi(data); };
+        var sc = FromRust.identity_mnemonic_generate(wordCount, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outPhrase == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outPhrase;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `Secp256K1` type from a BIP-39 mnemonic, derived along the ICP
+    /// path `m/44'/223'/0'/0/index`.
+    /// </summary>
+    public static Identity Secp256K1FromMnemonic(string phrase, string? passphrase = null, UInt32 index = 0)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_secp256k1_from_mnemonic(phrase, passphrase, index, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `Secp256K1` type from a Web3 Secret Storage (Ethereum keystore
+    /// v3) JSON.
+    /// </summary>
+    public static Identity Secp256K1FromWeb3Json(string json, string password)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_secp256k1_from_web3_json(json, password, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Export the [`Identity`] with `Secp256K1` type as a Web3 Secret Storage (Ethereum keystore
+    /// v3) JSON.
+    /// </summary>
+    public string ToWeb3Json(string password)
+    {
+        string? outJson = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) => { outJson = Marshal.PtrToStringAnsi(data); };
+        UnsizedCallback errCb = (data, len) => { outError = Marshal.PtrToStringAnsi(data); };
+        var sc = FromRust.identity_secp256k1_to_web3_json(_ptr, password, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outJson == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outJson;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `Delegated` type from the session identity and the delegation
+    /// chain in the JSON produced by agent-js.
+    /// </summary>
+    public static Identity DelegatedFromJson(Identity session, string json)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_delegated_from_json(session._ptr, json, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create a session [`Identity`] to be authorized by Internet Identity.
+    /// </summary>
+    public static Identity SessionRandom()
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_session_random(out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create the JSON of `authorize-client` message to be relayed to Internet Identity.
+    /// `maxTimeToLive` is in nanoseconds, `0` leaves it to Internet Identity.
+    /// </summary>
+    public string AuthorizeClientRequest(UInt64 maxTimeToLive, string? derivationOrigin)
+    {
+        string? outJson = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) => { outJson = Marshal.PtrToStringAnsi(data); };
+        UnsizedCallback errCb = (data, len) => { outError = Marshal.PtrToStringAnsi(data); };
+        var sc = FromRust.identity_authorize_client_request(_ptr, maxTimeToLive, derivationOrigin, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outJson == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outJson;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `Delegated` type from the message relayed back from Internet Identity.
+    /// </summary>
+    public static Identity DelegatedFromAuthorizeResponse(Identity session, string json)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_delegated_from_authorize_response(session._ptr, json, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create an [`Identity`] with `External` type, of which the key is kept outside, e.g. in the
+    /// Secure Enclave or the Android Keystore.
+    ///
+    /// `publicKey` is DER encoded, `signer` gives the signature of the message in the form which IC
+    /// expects, or null on failure.
+    /// </summary>
+    public static Identity External(byte[] publicKey, Func<byte[], byte[]?> signer)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+        ExternalSignCallback signCb = (context, message, messageLen, buf, bufLen) =>
+        {
+            var bytes = new byte[messageLen];
+            Marshal.Copy(message, bytes, 0, messageLen);
+
+            byte[]? signature;
+            try
+            {
+                signature = signer(bytes);
+            }
+            catch (Exception)
+            {
+                return -1;
+            }
+
+            if (signature == null || signature.Length > bufLen)
+                return -1;
+
+            Marshal.Copy(signature, 0, buf, signature.Length);
+            return signature.Length;
+        };
+
+        var sc = FromRust.identity_external(publicKey, publicKey.Length, signCb, IntPtr.Zero, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr) { _signCb = signCb };
         else
         {
             if (outError == null)
@@ -128,7 +431,7 @@ public class Identity
             outError = Marshal.PtrToStringAnsi(data);
         };
 
-        var sc = FromRust.identity_sender(_p2FPtr, retCb, errCb);
+        var sc = FromRust.identity_sender(_ptr, retCb, errCb);
 
         if (sc == StateCode.Ok)
         {
@@ -146,6 +449,106 @@ public class Identity
         }
     }
 
+    /// <summary>
+    /// Export the `Basic` or `Secp256K1` identity as PEM.
+    /// </summary>
+    public string ToPem()
+    {
+        string? outPem = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) => { outPem = Marshal.PtrToStringAnsi(data); };
+        UnsizedCallback errCb = (data, len) => { outError = Marshal.PtrToStringAnsi(data); };
+        var sc = FromRust.identity_to_pem(_ptr, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outPem == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outPem;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Export the raw 32 bytes secret key of the `Basic` or `Secp256K1` identity.
+    /// </summary>
+    public byte[] ToSecretKey()
+    {
+        byte[]? outBytes = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outBytes = new byte[len];
+            Marshal.Copy(data, outBytes, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_to_secret_key(_ptr, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outBytes == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outBytes;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// The DER encoded public key of the [`Identity`].
+    /// </summary>
+    public byte[] PublicKey()
+    {
+        byte[]? outBytes = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outBytes = new byte[len];
+            Marshal.Copy(data, outBytes, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_public_key(_ptr, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outBytes == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outBytes;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
     public (byte[], byte[]) Sign(byte[] bytes)
     {
         byte[]? publicKey = null;
@@ -167,7 +570,86 @@ public class Identity
             outError = Marshal.PtrToStringAnsi(data);
         };
 
-        var sc = FromRust.identity_sign(bytes, bytes.Length, _p2FPtr, pubKeyCb, sigCb, errCb);
+        var sc = FromRust.identity_sign(bytes, bytes.Length, _ptr, pubKeyCb, sigCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (publicKey == null || signature == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return (publicKey, signature);
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// The request id of the CBOR encoded content map of a call, query or read_state request.
+    /// </summary>
+    public static byte[] RequestId(byte[] content)
+    {
+        byte[]? outBytes = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outBytes = new byte[len];
+            Marshal.Copy(data, outBytes, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_request_id(content, content.Length, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outBytes == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outBytes;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Sign the request id of the CBOR encoded content map with the `\x0Aic-request` domain
+    /// separator, gives the public key and the `sender_sig` of the envelope.
+    /// </summary>
+    public (byte[], byte[]) SignRequest(byte[] content)
+    {
+        byte[]? publicKey = null;
+        byte[]? signature = null;
+        string? outError = null;
+
+        UnsizedCallback pubKeyCb = (data, len) =>
+        {
+            publicKey = new byte[len];
+            Marshal.Copy(data, publicKey, 0, len);
+        };
+        UnsizedCallback sigCb = (data, len) =>
+        {
+            signature = new byte[len];
+            Marshal.Copy(data, signature, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.identity_sign_request(content, content.Length, _ptr, pubKeyCb, sigCb, errCb);
 
         if (sc == StateCode.Ok)
         {
@@ -188,50 +670,181 @@ public class Identity
     internal static class FromRust
     {
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
-        internal static extern void identity_anonymous(IntPtr[] p2FPtr);
+        internal static extern void identity_anonymous(out IntPtr ptr);
 
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode identity_basic_random(
-            IntPtr[] p2FPtr,
+            out IntPtr ptr,
             UnsizedCallback errCb
         );
 
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode identity_basic_from_pem(
             [MarshalAs(UnmanagedType.LPStr)] string pem,
-            IntPtr[] p2FPtr,
+            out IntPtr ptr,
             UnsizedCallback errCb
         );
 
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
-        internal static extern void identity_secp256k1_random(IntPtr[] p2FPtr);
+        internal static extern void identity_secp256k1_random(out IntPtr ptr);
 
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode identity_secp256k1_from_pem(
             [MarshalAs(UnmanagedType.LPStr)] string pem,
-            IntPtr[] p2FPtr,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_basic_from_secret_key(
+            byte[] bytes,
+            Int32 bytesLen,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_secp256k1_from_secret_key(
+            byte[] bytes,
+            Int32 bytesLen,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_to_pem(
+            IntPtr ptr,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_to_secret_key(
+            IntPtr ptr,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_public_key(
+            IntPtr ptr,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_mnemonic_generate(
+            UInt32 wordCount,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_secp256k1_from_mnemonic(
+            [MarshalAs(UnmanagedType.LPStr)] string phrase,
+            [MarshalAs(UnmanagedType.LPStr)] string? passphrase,
+            UInt32 index,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_secp256k1_from_web3_json(
+            [MarshalAs(UnmanagedType.LPStr)] string json,
+            [MarshalAs(UnmanagedType.LPStr)] string pwd,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_secp256k1_to_web3_json(
+            IntPtr ptr,
+            [MarshalAs(UnmanagedType.LPStr)] string pwd,
+            UnsizedCallback retCb,
             UnsizedCallback errCb
         );
 
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode identity_sender(
-            IntPtr[] p2FPtr,
+            IntPtr ptr,
             UnsizedCallback retCb,
             UnsizedCallback errCb
         );
 
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_request_id(
+            byte[] content,
+            Int32 contentLen,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_sign_request(
+            byte[] content,
+            Int32 contentLen,
+            IntPtr ptr,
+            UnsizedCallback pubKeyCb,
+            UnsizedCallback sigCb,
+            UnsizedCallback errCb
+        );
+
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
         internal static extern StateCode identity_sign(
             byte[] bytes,
             Int32 bytesLen,
-            IntPtr[] p2FPtr,
+            IntPtr ptr,
             UnsizedCallback pubKeyCb,
             UnsizedCallback sigCb,
             UnsizedCallback errCb
         );
 
         [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
-        internal static extern StateCode identity_free(IntPtr[] p2FPtr);
+        internal static extern StateCode identity_delegated_from_json(
+            IntPtr ptrSession,
+            [MarshalAs(UnmanagedType.LPStr)] string json,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_session_random(
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_authorize_client_request(
+            IntPtr ptrSession,
+            UInt64 maxTimeToLive,
+            [MarshalAs(UnmanagedType.LPStr)] string? derivationOrigin,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_delegated_from_authorize_response(
+            IntPtr ptrSession,
+            [MarshalAs(UnmanagedType.LPStr)] string json,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode identity_external(
+            byte[] publicKey,
+            Int32 publicKeyLen,
+            ExternalSignCallback signCb,
+            IntPtr context,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern IdentityType identity_type(IntPtr ptr);
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void identity_free(IntPtr ptr);
     }
 }
 
@@ -240,5 +853,9 @@ public enum IdentityType
     Anonymous = 0,
     Basic = 1,
     Secp256K1 = 2,
+    Delegated = 3,
+    External = 4,
 }
+
+internal delegate Int32 ExternalSignCallback(IntPtr context, IntPtr message, Int32 messageLen, IntPtr buf, Int32 bufLen);
 #nullable disable
\ No newline at end of file
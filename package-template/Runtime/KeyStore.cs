@@ -0,0 +1,290 @@
+using System;
+using System.Runtime.InteropServices;
+
+#nullable enable
+public class KeyStore
+{
+    private IntPtr _ptr;
+
+    private KeyStore(IntPtr ptr)
+    {
+        this._ptr = ptr;
+    }
+
+    ~KeyStore()
+    {
+        FromRust.keystore_free(_ptr);
+    }
+
+    /// <summary>
+    /// The version of the format of [`KeyStore`].
+    /// </summary>
+    public UInt32 Version => FromRust.keystore_version(_ptr);
+
+    /// <summary>
+    /// Create a [`KeyStore`] of a random Ed25519 key, encrypted by the password.
+    /// </summary>
+    public KeyStore(string name, string password)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_create(name, password, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            this._ptr = ptr;
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create a [`KeyStore`] of a random Secp256k1 key, encrypted by the password.
+    /// </summary>
+    public static KeyStore Secp256K1(string name, string password)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_create_secp256k1(name, password, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new KeyStore(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Create a [`KeyStore`] of the secret key of an existing identity, e.g. an imported wallet
+    /// key, encrypted by the password.
+    /// </summary>
+    public static KeyStore FromIdentity(string name, string password, Identity identity)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_from_identity(name, password, identity._ptr, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new KeyStore(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public static KeyStore FromJsonStr(string json)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_from_json(json, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new KeyStore(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public string ToJsonStr()
+    {
+        string? outJson = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) => { outJson = Marshal.PtrToStringAnsi(data); };
+        UnsizedCallback errCb = (data, len) => { outError = Marshal.PtrToStringAnsi(data); };
+        var sc = FromRust.keystore_to_json(_ptr, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outJson == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return outJson;
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public void ChangePassword(string oldPassword, string newPassword)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_change_password(_ptr, oldPassword, newPassword, errCb);
+
+        if (sc != StateCode.Ok)
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    /// <summary>
+    /// Decrypt the [`KeyStore`] into an [`Identity`].
+    /// </summary>
+    public Identity ToIdentity(string password)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_to_identity(_ptr, password, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new Identity(ptr);
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    public Principal Principal()
+    {
+        byte[]? outBytes = null;
+        string? outError = null;
+
+        UnsizedCallback retCb = (data, len) =>
+        {
+            outBytes = new byte[len];
+            Marshal.Copy(data, outBytes, 0, len);
+        };
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.keystore_principal(_ptr, retCb, errCb);
+
+        if (sc == StateCode.Ok)
+        {
+            if (outBytes == null)
+                throw new FailedCallingRust("Failed on calling function of rust.");
+            else
+                return new Principal(outBytes);
+        }
+        else
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    internal static class FromRust
+    {
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_create(
+            [MarshalAs(UnmanagedType.LPStr)] string name,
+            [MarshalAs(UnmanagedType.LPStr)] string pwd,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_create_secp256k1(
+            [MarshalAs(UnmanagedType.LPStr)] string name,
+            [MarshalAs(UnmanagedType.LPStr)] string pwd,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_from_identity(
+            [MarshalAs(UnmanagedType.LPStr)] string name,
+            [MarshalAs(UnmanagedType.LPStr)] string pwd,
+            IntPtr ptr2Identity,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_from_json(
+            [MarshalAs(UnmanagedType.LPStr)] string json,
+            out IntPtr ptr,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_to_json(
+            IntPtr ptr,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_change_password(
+            IntPtr ptr,
+            [MarshalAs(UnmanagedType.LPStr)] string oldPwd,
+            [MarshalAs(UnmanagedType.LPStr)] string newPwd,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_to_identity(
+            IntPtr ptr,
+            [MarshalAs(UnmanagedType.LPStr)] string pwd,
+            out IntPtr ptrIdentity,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode keystore_principal(
+            IntPtr ptr,
+            UnsizedCallback retCb,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern UInt32 keystore_version(IntPtr ptr);
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void keystore_free(IntPtr ptr);
+    }
+}
+#nullable disable
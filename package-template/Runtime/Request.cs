@@ -0,0 +1,74 @@
+using System;
+using System.Runtime.InteropServices;
+using Candid;
+
+#nullable enable
+/// <summary>
+/// A call running in the background, given by `Agent.QueryBegin` or `Agent.UpdateBegin`, of which
+/// the result is polled, e.g. once per frame. The call is aborted if it's collected while running.
+/// </summary>
+public class Request
+{
+    internal IntPtr _ptr;
+
+    internal Request(IntPtr ptr)
+    {
+        _ptr = ptr;
+    }
+
+    ~Request()
+    {
+        FromRust.request_free(_ptr);
+    }
+
+    /// <summary>
+    /// `StateCode.Pending` while running, then `StateCode.Ok` or `StateCode.Err`.
+    /// </summary>
+    public StateCode Poll()
+    {
+        return FromRust.request_poll(_ptr);
+    }
+
+    /// <summary>
+    /// Move the result out of the finished call, gives null if it's still running. Throws if the
+    /// call failed or the result has been taken.
+    /// </summary>
+    public IDLArgs? TakeResult()
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var sc = FromRust.request_take_result(_ptr, out IntPtr ptr, errCb);
+
+        if (sc == StateCode.Ok)
+            return new IDLArgs(ptr);
+
+        if (sc == StateCode.Pending)
+            return null;
+
+        if (outError == null)
+            throw new FailedCallingRust("Failed on getting error from rust.");
+
+        throw new ErrorFromRust(outError);
+    }
+
+    internal static class FromRust
+    {
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode request_poll(IntPtr ptr2Request);
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode request_take_result(
+            IntPtr ptr2Request,
+            out IntPtr ptr2Args,
+            UnsizedCallback errCb
+        );
+
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void request_free(IntPtr ptr2Request);
+    }
+}
+#nullable disable
@@ -0,0 +1,54 @@
+using System;
+using System.Runtime.InteropServices;
+
+#nullable enable
+public static class Signature
+{
+    /// <summary>
+    /// Verify the signature of the message by the DER encoded public key, the algorithm is
+    /// detected from the key: Ed25519, Secp256k1 or P-256. Throws if the signature is invalid.
+    /// </summary>
+    /// <param name="principal">If given, the key has to be the one of this self-authenticating principal.</param>
+    public static void Verify(byte[] publicKey, byte[] message, byte[] signature, Principal? principal = null)
+    {
+        string? outError = null;
+        UnsizedCallback errCb = (data, len) =>
+        {
+            outError = Marshal.PtrToStringAnsi(data);
+        };
+
+        var principalBytes = principal?.Bytes;
+        var sc = FromRust.signature_verify(
+            publicKey, publicKey.Length,
+            message, message.Length,
+            signature, signature.Length,
+            principalBytes, principalBytes?.Length ?? 0,
+            errCb
+        );
+
+        if (sc != StateCode.Ok)
+        {
+            if (outError == null)
+                throw new FailedCallingRust("Failed on getting error from rust.");
+            else
+                throw new ErrorFromRust(outError);
+        }
+    }
+
+    internal static class FromRust
+    {
+        [DllImport("ic-agent", CallingConvention = CallingConvention.Cdecl)]
+        internal static extern StateCode signature_verify(
+            byte[] publicKey,
+            Int32 publicKeyLen,
+            byte[] message,
+            Int32 messageLen,
+            byte[] signature,
+            Int32 signatureLen,
+            byte[]? principal,
+            Int32 principalLen,
+            UnsizedCallback errCb
+        );
+    }
+}
+#nullable disable